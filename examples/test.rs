@@ -11,8 +11,9 @@ use tinyhttp::config::HttpConfig;
 use tinyhttp::error::Error;
 use tinyhttp::reader::RequestReader;
 use tinyhttp::status::StatusCode;
+use tinyhttp::transport::Transport;
 use tinyhttp::writer::{HttpResponse, ResponseWriter};
-use tinyhttp::{router, HttpServer};
+use tinyhttp::{router, HttpServer, ServerResources};
 
 #[derive(Parser)]
 #[clap(version = "1.0")]
@@ -54,7 +55,7 @@ async fn main_task(spawner: Spawner) {
     let seed = u64::from_le_bytes(seed);
 
     // Init network stack
-    static RESOURCES: StaticCell<StackResources<2>> = StaticCell::new();
+    static RESOURCES: StaticCell<StackResources<5>> = StaticCell::new();
     let (stack, runner) =
         embassy_net::new(device, config, RESOURCES.init(StackResources::new()), seed);
 
@@ -68,21 +69,21 @@ async fn http_task(stack: embassy_net::Stack<'static>) {
     // Then we can use it!
     let config = HttpConfig::default();
 
-    let mut tx_buf = [0u8; 1024];
-    let mut rx_buf = [0u8; 1024];
-    let mut http_buf = [0u8; 2048];
+    // serve up to 4 clients at once
+    static SERVER_RESOURCES: StaticCell<ServerResources<4>> = StaticCell::new();
+    let resources = SERVER_RESOURCES.init(ServerResources::new());
 
     HttpServer::new(stack, &config)
         .route(router! {
             "/" => send_204,
         })
-        .run(&mut tx_buf, &mut rx_buf, &mut http_buf)
+        .run_pool(resources)
         .await;
 }
 
-async fn send_204(
-    _reader: RequestReader<'_, '_, '_>,
-    writer: ResponseWriter<'_, '_>,
+async fn send_204<T: Transport>(
+    _reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
 ) -> Result<HttpResponse, Error> {
     writer
         .start(StatusCode::NO_CONTENT)
@@ -0,0 +1,141 @@
+//! Procedural macros for `tinyhttp`, use them through its `embed` feature.
+
+use std::{
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use proc_macro::{TokenStream, TokenTree};
+
+/// Embeds every file of a directory, relative to the crate root, into a `tinyhttp::files::StaticDir`.
+///
+/// Files are served at their path relative to the directory, and `index.html` files also at the path of their directory.
+/// Hidden files are skipped.
+///
+/// Files with a `.gz` or `.br` precompressed variant next to them, like `app.js.gz`, are sent compressed
+/// to the clients that accept it.
+///
+/// Files that are added to the directory are only picked up once the crate is rebuilt for another reason.
+#[proc_macro]
+pub fn embed_dir(input: TokenStream) -> TokenStream {
+    match expand(input) {
+        Ok(tokens) => tokens,
+        Err(message) => compile_error(&message),
+    }
+}
+
+fn expand(input: TokenStream) -> Result<TokenStream, String> {
+    let dir = parse_path(input)?;
+
+    let root = std::env::var("CARGO_MANIFEST_DIR").map_err(|e| e.to_string())?;
+    let root = Path::new(&root).join(dir);
+
+    let mut files = Vec::new();
+    collect_files(&root, "", &mut files)
+        .map_err(|e| format!("can't read directory `{}`: {e}", root.display()))?;
+    // the server looks the files up with a binary search
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let variant = |path: &str, extension: &str| {
+        let path = format!("{path}.{extension}");
+        files.iter().find(|(p, _)| *p == path).map(|(_, file)| file)
+    };
+    // precompressed variants are sent with their original file, instead of on their own
+    let is_variant = |path: &str| {
+        [".gz", ".br"].into_iter().any(|extension| {
+            path.strip_suffix(extension)
+                .is_some_and(|original| files.iter().any(|(p, _)| p == original))
+        })
+    };
+
+    let mut code = String::from("::tinyhttp::files::StaticDir::new(&[");
+    for (path, file) in files.iter().filter(|(path, _)| !is_variant(path)) {
+        let body = fs::read(file).map_err(|e| format!("can't read `{}`: {e}", file.display()))?;
+
+        let extension = file
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+
+        // the content type is picked by tinyhttp, like for the pages created by hand
+        let mut page = format!(
+            "::tinyhttp::config::StaticPage::from_extension({extension:?}, ::core::include_bytes!({file:?}))"
+        );
+        if let Some(gzip) = variant(path, "gz") {
+            write!(page, ".with_gzip(::core::include_bytes!({gzip:?}))")
+                .map_err(|e| e.to_string())?;
+        }
+        if let Some(br) = variant(path, "br") {
+            write!(page, ".with_br(::core::include_bytes!({br:?}))").map_err(|e| e.to_string())?;
+        }
+
+        write!(
+            code,
+            "::tinyhttp::files::StaticFile {{ path: {path:?}, page: {page}.with_etag({:#018x}) }},",
+            fnv1a(&body),
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    code.push_str("])");
+
+    TokenStream::from_str(&code).map_err(|e| e.to_string())
+}
+
+/// Parses the string literal passed to the macro.
+fn parse_path(input: TokenStream) -> Result<String, String> {
+    let mut tokens = input.into_iter();
+
+    match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => {
+            let literal = literal.to_string();
+
+            literal
+                .strip_prefix('"')
+                .and_then(|l| l.strip_suffix('"'))
+                .filter(|l| !l.contains('\\'))
+                .map(String::from)
+                .ok_or_else(|| "expected a path without escapes".into())
+        }
+        _ => Err("expected the path of a directory, like `embed_dir!(\"static\")`".into()),
+    }
+}
+
+/// Recursively finds the files of `dir`, with the path they're served at.
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(String::from) else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let path = format!("{prefix}/{name}");
+        let file = fs::canonicalize(entry.path())?;
+
+        if entry.file_type()?.is_dir() {
+            collect_files(&file, &path, files)?;
+        } else {
+            files.push((path, file));
+        }
+    }
+
+    Ok(())
+}
+
+/// 64 bit FNV-1a hash of the content of a file, to tell versions of it apart.
+///
+/// It's the hash of `StaticPage::hashed`, computed here because hashing large files in const eval
+/// takes seconds per megabyte and trips the `long_running_const_eval` lint.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn compile_error(message: &str) -> TokenStream {
+    TokenStream::from_str(&format!("::core::compile_error!({message:?})")).unwrap_or_default()
+}
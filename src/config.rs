@@ -1,3 +1,15 @@
+use core::fmt::Write;
+
+use crate::{
+    encoding::{AcceptEncoding, ContentCoding},
+    headers::HeaderName,
+    request::HttpRequest,
+    utils,
+};
+
+/// Maximum length of the ETag of a [`StaticPage`].
+pub(crate) const ETAG_LEN: usize = 32;
+
 #[cfg(feature = "default_error_pages")]
 /// The default HTTP 400 Bad Request page
 pub const DEFAULT_400: StaticPage = StaticPage::html(include_str!("../static/400.html"));
@@ -19,11 +31,36 @@ pub struct HttpConfig<'a> {
     pub port: u16,
     /// Number of seconds each TCP socket is allowed to be kept alive for without any data transfer.
     ///
-    /// If None, the connection is closed immediately.
+    /// While a connection is idle, the server waits this long for the client to send another request.
+    /// Clients can still ask to close the connection with the `Connection: close` header.
+    ///
+    /// If None, the connection is closed immediately after each response.
     ///
     /// Default: 5 seconds.
     pub keepalive: Option<u16>,
 
+    /// Number of seconds a client has to send all of the headers of a request, once it started sending it.
+    ///
+    /// If the headers don't arrive in time, a 408 Request Timeout error code is sent.
+    ///
+    /// Default: 5 seconds.
+    pub header_timeout: u16,
+
+    /// Maximum number of bytes of a request body that are discarded if the handler doesn't read them,
+    /// to be able to read the next request on the same connection.
+    ///
+    /// If more than this is left, the connection is closed instead.
+    ///
+    /// Default: 4096 bytes.
+    pub body_drain_limit: usize,
+
+    /// Cross-Origin Resource Sharing settings, to let web pages from other origins use the server.
+    ///
+    /// If None, no CORS headers are sent, and browsers only allow pages served by the server itself to use it.
+    ///
+    /// Default: None
+    pub cors: Option<CorsConfig<'a>>,
+
     /// Username/Password combo for global basic authentication.
     ///
     /// Default: None
@@ -57,6 +94,9 @@ impl Default for HttpConfig<'static> {
         Self {
             port: 80,
             keepalive: Some(5),
+            header_timeout: 5,
+            body_drain_limit: 4096,
+            cors: None,
             #[cfg(feature = "global_http_basic_auth")]
             basic_auth: None,
             http_400: None,
@@ -71,6 +111,9 @@ impl Default for HttpConfig<'static> {
         Self {
             port: 80,
             keepalive: Some(5),
+            header_timeout: 5,
+            body_drain_limit: 4096,
+            cors: None,
             #[cfg(feature = "global_http_basic_auth")]
             basic_auth: None,
             http_400: Some(DEFAULT_400),
@@ -81,33 +124,168 @@ impl Default for HttpConfig<'static> {
     }
 }
 
+/// Cross-Origin Resource Sharing settings.
+///
+/// Preflight requests are answered automatically by [`crate::router!`],
+/// with the methods the requested path accepts.
+#[derive(Debug, Clone, Copy)]
+pub struct CorsConfig<'a> {
+    /// Origins allowed to use the server, like `http://192.168.1.10:8080`.
+    ///
+    /// `*` allows any origin, but only if `allow_credentials` is false.
+    pub allowed_origins: &'a [&'a str],
+
+    /// Comma separated list of the request headers clients are allowed to send, like `Content-Type, Authorization`.
+    ///
+    /// If None, every header a preflight request asks for is allowed.
+    pub allowed_headers: Option<&'a str>,
+
+    /// Comma separated list of the response headers clients are allowed to read, besides the default ones.
+    pub exposed_headers: Option<&'a str>,
+
+    /// Number of seconds clients can cache the result of a preflight request for.
+    pub max_age: Option<u32>,
+
+    /// Whether clients are allowed to send credentials, like cookies or the `Authorization` header.
+    pub allow_credentials: bool,
+}
+
+impl<'a> CorsConfig<'a> {
+    /// Gets the value of the `Access-Control-Allow-Origin` header for a request,
+    /// or None if it isn't a cross-origin request or its origin isn't allowed.
+    pub(crate) fn allow_origin(&self, request: &HttpRequest) -> Option<&'a str> {
+        let origin = request.try_find_header(&HeaderName::Origin)?.trim();
+
+        self.allowed_origins
+            .iter()
+            .copied()
+            .find(|allowed| *allowed == origin || (*allowed == "*" && !self.allow_credentials))
+    }
+}
+
 /// Represents a static page, loaded from flash.
-/// Currently only supports uncompressed pages.
+///
+/// Pages can have precompressed variants, which are sent to the clients that accept them.
 #[derive(Debug, Clone, Copy)]
 pub struct StaticPage<'a> {
-    pub(crate) content_type: &'a str,
-    pub(crate) body: &'a str,
+    pub(crate) content_type: ContentType<'a>,
+    pub(crate) body: &'a [u8],
+    pub(crate) gzip: Option<&'a [u8]>,
+    pub(crate) br: Option<&'a [u8]>,
+    /// Hash of the body, sent as a strong ETag.
+    pub(crate) etag: Option<u64>,
+    pub(crate) cache_control: Option<&'a str>,
 }
 
 impl<'a> StaticPage<'a> {
-    pub const fn html(body: &'a str) -> Self {
+    /// Creates a page with any content type, like `image/png`.
+    pub const fn new(body: &'a [u8], content_type: &'a str) -> Self {
         StaticPage {
-            content_type: "text/html; charset=UTF-8",
+            content_type: ContentType::Str(content_type),
             body,
+            gzip: None,
+            br: None,
+            etag: None,
+            cache_control: None,
         }
     }
 
-    pub const fn text(body: &'a str) -> Self {
+    /// Creates a page whose content type is guessed from a file extension, like `ico` or `wasm`.
+    ///
+    /// The MIME type is looked up when the page is sent. Text types are sent as UTF-8,
+    /// and unknown extensions as `application/octet-stream`.
+    pub const fn from_extension(extension: &'a str, body: &'a [u8]) -> Self {
         StaticPage {
-            content_type: "text/plain; charset=UTF-8",
+            content_type: ContentType::Extension(extension),
             body,
+            gzip: None,
+            br: None,
+            etag: None,
+            cache_control: None,
         }
     }
 
-    pub const fn json(body: &'a str) -> Self {
-        StaticPage {
-            content_type: "text/json; charset=UTF-8",
-            body,
+    /// Adds a gzip compressed variant of the body.
+    pub const fn with_gzip(mut self, body: &'a [u8]) -> Self {
+        self.gzip = Some(body);
+        self
+    }
+
+    /// Adds a brotli compressed variant of the body.
+    pub const fn with_br(mut self, body: &'a [u8]) -> Self {
+        self.br = Some(body);
+        self
+    }
+
+    /// Sets the ETag of the page to `hash`, which must change whenever the body changes.
+    ///
+    /// Clients that already have the page get a 304 Not Modified, see [`crate::writer::HttpWriter::static_page`].
+    pub const fn with_etag(mut self, hash: u64) -> Self {
+        self.etag = Some(hash);
+        self
+    }
+
+    /// Sets the ETag of the page to a hash of the body.
+    ///
+    /// Use it in a `const` or a `static`, so the hash is computed at compile time.
+    pub const fn hashed(self) -> Self {
+        let hash = utils::fnv1a(self.body);
+        self.with_etag(hash)
+    }
+
+    /// Sets the `Cache-Control` header sent with the page,
+    /// like `no-cache` to always revalidate it, or `max-age=31536000, immutable` for assets that never change.
+    pub const fn with_cache_control(mut self, cache_control: &'a str) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
+
+    /// Formats the ETag of the variant of the page sent with `coding`.
+    pub(crate) fn etag(&self, coding: ContentCoding) -> Option<heapless::String<ETAG_LEN>> {
+        let hash = self.etag?;
+        let mut etag = heapless::String::new();
+
+        // every variant needs its own strong ETag
+        match coding {
+            ContentCoding::Identity => write!(etag, "\"{hash:016x}\""),
+            coding => write!(etag, "\"{hash:016x}-{}\"", coding.as_str()),
+        }
+        .ok()?;
+
+        Some(etag)
+    }
+
+    /// Picks the variant of the body to send to a client.
+    pub(crate) fn encoded(&self, accept: &AcceptEncoding) -> (ContentCoding, &'a [u8]) {
+        match accept.choose(self.gzip.is_some(), self.br.is_some()) {
+            ContentCoding::Gzip => (ContentCoding::Gzip, self.gzip.unwrap_or(self.body)),
+            ContentCoding::Br => (ContentCoding::Br, self.br.unwrap_or(self.body)),
+            ContentCoding::Identity => (ContentCoding::Identity, self.body),
         }
     }
+
+    /// Whether the page has compressed variants.
+    pub(crate) fn is_compressed(&self) -> bool {
+        self.gzip.is_some() || self.br.is_some()
+    }
+
+    pub const fn html(body: &'a str) -> Self {
+        Self::new(body.as_bytes(), "text/html; charset=UTF-8")
+    }
+
+    pub const fn text(body: &'a str) -> Self {
+        Self::new(body.as_bytes(), "text/plain; charset=UTF-8")
+    }
+
+    pub const fn json(body: &'a str) -> Self {
+        Self::new(body.as_bytes(), "application/json")
+    }
+}
+
+/// The content type of a [`StaticPage`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ContentType<'a> {
+    Str(&'a str),
+    /// The extension of the file, whose MIME type is guessed when it's sent.
+    Extension(&'a str),
 }
@@ -0,0 +1,638 @@
+//! HTTP digest authentication (RFC 7616), so that passwords are never sent in cleartext.
+//!
+//! ```ignore
+//! // reads the hardware random number generator
+//! struct HardwareRng;
+//!
+//! impl RngCore for HardwareRng { ... }
+//!
+//! static DIGEST: DigestAuth<CriticalSectionRawMutex, HardwareRng> =
+//!     DigestAuth::new("admin", &[("admin", "hunter2")], HardwareRng);
+//!
+//! router! {
+//!     "/admin/*" => admin [auth = DIGEST],
+//! }
+//! ```
+//!
+//! Clients can use SHA-256 or, for older ones, MD5. Only the `auth` quality of protection is supported.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{raw::RawMutex, Mutex};
+use rand_core::RngCore;
+use sha2::Digest;
+
+use crate::{
+    auth::{constant_time_eq, Authenticator},
+    config::HttpConfig,
+    error::Error,
+    headers::HeaderName,
+    request::{HttpMethod, HttpRequest},
+    status::StatusCode,
+    transport::Transport,
+    writer::{HttpResponse, ResponseWriter},
+};
+
+/// Length of the random nonces issued by the server, in bytes.
+const NONCE_LEN: usize = 16;
+
+/// Protects routes with HTTP digest authentication, see [`crate::router!`].
+///
+/// Every challenge sent to a client has a new nonce, taken from `R`.
+/// The last `N` nonces are remembered with the last nonce count they were used with,
+/// and requests that reuse a nonce count are rejected, so that they can't be replayed.
+/// Those requests, and the ones whose nonce was evicted from the table, have valid credentials,
+/// so their clients are asked to retry with a new nonce without prompting the user.
+///
+/// The state is behind a [`Mutex`], so a `DigestAuth` can be shared between the tasks of [`crate::RoutableHttpServer::run_pool`].
+pub struct DigestAuth<'a, M: RawMutex, R, C: ?Sized = [(&'a str, &'a str)], const N: usize = 8> {
+    realm: &'a str,
+    credentials: &'a C,
+    nonces: Mutex<M, RefCell<Nonces<R, N>>>,
+}
+
+impl<'a, M: RawMutex, R: RngCore, C: DigestCredentials + ?Sized, const N: usize>
+    DigestAuth<'a, M, R, C, N>
+{
+    /// Creates a digest authenticator for `realm`, which takes its nonces from `rng`.
+    ///
+    /// `rng` should be a cryptographically secure random number generator, like a hardware one.
+    pub const fn new(realm: &'a str, credentials: &'a C, rng: R) -> Self {
+        Self {
+            realm,
+            credentials,
+            nonces: Mutex::new(RefCell::new(Nonces {
+                rng,
+                table: [None; N],
+                next: 0,
+            })),
+        }
+    }
+
+    /// Validates the `Authorization: Digest` header of a request.
+    async fn validate(&self, request: &HttpRequest<'_>) -> Result<&'a str, Invalid> {
+        let header = request
+            .try_find_header(&HeaderName::Authorization)
+            .ok_or(Invalid::Credentials)?;
+        let params = DigestParams::parse(header).ok_or(Invalid::Credentials)?;
+
+        if params.realm != self.realm || !matches_target(request, params.uri) {
+            return Err(Invalid::Credentials);
+        }
+
+        let nonce = decode_nonce(params.nonce).ok_or(Invalid::Credentials)?;
+
+        // unknown users go through the whole check too, so that they can't be told apart from the time taken
+        let mut ha1 = [0u8; MAX_HEX_LEN];
+        let user = self
+            .credentials
+            .ha1(params.username, self.realm, params.algorithm, &mut ha1)
+            .await;
+        let ha1 = &ha1[..params.algorithm.hex_len()];
+
+        let mut expected = [0u8; MAX_HEX_LEN];
+        let expected = params.expected_response(request.method(), ha1, &mut expected);
+
+        // clients may send the hash in uppercase
+        let mut response = [0u8; MAX_HEX_LEN];
+        let response = response
+            .get_mut(..params.response.len())
+            .ok_or(Invalid::Credentials)?;
+        response.copy_from_slice(params.response.as_bytes());
+        response.make_ascii_lowercase();
+
+        let valid = constant_time_eq(expected, response);
+        let Some(user) = user.filter(|_| valid) else {
+            return Err(Invalid::Credentials);
+        };
+
+        // only count the nonce as used once the client proved it knows the password,
+        // and only report it as stale then, so that clients without the password are never asked to retry silently
+        let nc = u32::from_str_radix(params.nc, 16).map_err(|_| Invalid::Credentials)?;
+        match self
+            .nonces
+            .lock(|nonces| nonces.borrow_mut().use_count(&nonce, nc))
+        {
+            Some(true) => Ok(user),
+            // parallel requests on the same nonce can arrive out of order, they're retried with a new nonce
+            Some(false) | None => Err(Invalid::Stale),
+        }
+    }
+}
+
+impl<'a, M: RawMutex, R: RngCore, C: DigestCredentials + ?Sized, const N: usize> Authenticator<'a>
+    for DigestAuth<'a, M, R, C, N>
+{
+    type Rejection = Invalid;
+
+    async fn check(&self, request: &HttpRequest<'_>) -> Result<&'a str, Invalid> {
+        self.validate(request).await
+    }
+
+    async fn challenge<'c, T: Transport>(
+        &self,
+        config: &HttpConfig<'c>,
+        rejection: Invalid,
+        writer: ResponseWriter<'c, '_, T>,
+    ) -> Result<HttpResponse, Error> {
+        // a request with valid credentials but a stale nonce can simply be retried with a new one
+        let stale = rejection == Invalid::Stale;
+
+        let nonce = self.nonces.lock(|nonces| nonces.borrow_mut().issue());
+        let mut hex = [0u8; NONCE_LEN * 2];
+        let nonce = encode_hex(&nonce, &mut hex);
+        let stale = if stale { ", stale=true" } else { "" };
+
+        // the preferred algorithm goes first
+        let mut writer = writer.start(StatusCode::UNAUTHORIZED).await?;
+        for algorithm in ["SHA-256", "MD5"] {
+            writer = writer
+                .header_parts(
+                    "WWW-Authenticate",
+                    &[
+                        "Digest realm=\"",
+                        self.realm,
+                        "\", qop=\"auth\", algorithm=",
+                        algorithm,
+                        ", nonce=\"",
+                        nonce,
+                        "\"",
+                        stale,
+                    ],
+                )
+                .await?;
+        }
+
+        match config.http_401 {
+            Some(page) => writer.body_static_page(page).await,
+            None => writer.body_empty().await,
+        }
+    }
+}
+
+/// Why a request doesn't have valid credentials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invalid {
+    /// The credentials are missing or wrong.
+    Credentials,
+    /// The credentials are valid, but the nonce isn't known, because it was evicted or issued before a reboot,
+    /// or its nonce count was already used.
+    Stale,
+}
+
+/// Source of the credentials that are allowed to access a [`DigestAuth`] protected route.
+///
+/// Digest authentication only needs `H(username:realm:password)`, so stores can keep that instead of the passwords.
+#[allow(async_fn_in_trait)]
+pub trait DigestCredentials {
+    /// Looks up a user, writing the hex encoded `H(username:realm:password)` with the `algorithm` hash to `ha1`.
+    ///
+    /// Returns the name of the user if it exists.
+    /// Usernames should be compared with [`constant_time_eq`], and a hash written for unknown users too,
+    /// so that they can't be told apart from the time taken.
+    async fn ha1(
+        &self,
+        username: &str,
+        realm: &str,
+        algorithm: Algorithm,
+        ha1: &mut [u8; MAX_HEX_LEN],
+    ) -> Option<&str>;
+}
+
+impl DigestCredentials for [(&str, &str)] {
+    async fn ha1(
+        &self,
+        username: &str,
+        realm: &str,
+        algorithm: Algorithm,
+        ha1: &mut [u8; MAX_HEX_LEN],
+    ) -> Option<&str> {
+        let mut user = None;
+
+        // check every user, so that the time taken doesn't depend on which one matches
+        for entry in self {
+            if constant_time_eq(entry.0.as_bytes(), username.as_bytes()) & user.is_none() {
+                user = Some(entry);
+            }
+        }
+
+        // unknown users get a hash computed as well, so that they can't be told apart from the time taken
+        let (name, pass) = match user {
+            Some((name, pass)) => (*name, *pass),
+            None => (username, ""),
+        };
+        algorithm.hex_digest(&[name.as_bytes(), realm.as_bytes(), pass.as_bytes()], ha1);
+
+        user.map(|(name, _)| *name)
+    }
+}
+
+/// Length of the longest hex encoded hash.
+pub const MAX_HEX_LEN: usize = 64;
+
+/// The hash function used for digest authentication.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Only for older clients, MD5 is broken.
+    Md5,
+    Sha256,
+}
+
+impl Algorithm {
+    /// Length of the hex encoded hashes.
+    pub fn hex_len(self) -> usize {
+        match self {
+            Algorithm::Md5 => 32,
+            Algorithm::Sha256 => 64,
+        }
+    }
+
+    /// Hashes `parts` joined with `:`, writing the lowercase hex encoded hash to `out`.
+    pub fn hex_digest<'o>(self, parts: &[&[u8]], out: &'o mut [u8; MAX_HEX_LEN]) -> &'o [u8] {
+        fn hash<D: Digest>(parts: &[&[u8]], out: &mut [u8]) -> usize {
+            let mut hasher = D::new();
+            for (i, part) in parts.iter().enumerate() {
+                if i > 0 {
+                    hasher.update(b":");
+                }
+                hasher.update(part);
+            }
+
+            encode_hex(&hasher.finalize(), out).len()
+        }
+
+        let len = match self {
+            Algorithm::Md5 => hash::<md5::Md5>(parts, out),
+            Algorithm::Sha256 => hash::<sha2::Sha256>(parts, out),
+        };
+
+        &out[..len]
+    }
+}
+
+/// The nonces that were sent to clients.
+struct Nonces<R, const N: usize> {
+    rng: R,
+    table: [Option<Nonce>; N],
+    /// The slot of the next nonce, the oldest one is overwritten once the table is full.
+    next: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Nonce {
+    value: [u8; NONCE_LEN],
+    /// The last nonce count used by the client.
+    count: u32,
+}
+
+impl<R: RngCore, const N: usize> Nonces<R, N> {
+    fn issue(&mut self) -> [u8; NONCE_LEN] {
+        let mut value = [0u8; NONCE_LEN];
+        self.rng.fill_bytes(&mut value);
+
+        if let Some(slot) = self.table.get_mut(self.next) {
+            *slot = Some(Nonce { value, count: 0 });
+            self.next = (self.next + 1) % N;
+        }
+
+        value
+    }
+
+    /// Records that a nonce was used with the nonce count `count`.
+    ///
+    /// Returns whether the count is higher than the previous one, or [`None`] if the nonce isn't known.
+    fn use_count(&mut self, value: &[u8; NONCE_LEN], count: u32) -> Option<bool> {
+        let nonce = self
+            .table
+            .iter_mut()
+            .flatten()
+            .find(|nonce| nonce.value == *value)?;
+
+        if count <= nonce.count {
+            return Some(false);
+        }
+        nonce.count = count;
+
+        Some(true)
+    }
+}
+
+/// The parameters of an `Authorization: Digest` header.
+struct DigestParams<'a> {
+    username: &'a str,
+    realm: &'a str,
+    uri: &'a str,
+    algorithm: Algorithm,
+    nonce: &'a str,
+    nc: &'a str,
+    cnonce: &'a str,
+    response: &'a str,
+}
+
+impl<'a> DigestParams<'a> {
+    fn parse(header: &'a str) -> Option<Self> {
+        let (scheme, mut rest) = header.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("Digest") {
+            return None;
+        }
+
+        let (mut username, mut realm, mut uri, mut nonce, mut nc, mut cnonce, mut response) =
+            (None, None, None, None, None, None, None);
+        let mut qop = None;
+        // MD5 is the default, for clients that predate RFC 7616
+        let mut algorithm = Algorithm::Md5;
+
+        while !rest.is_empty() {
+            let (name, after) = rest.split_once('=')?;
+            let after = after.trim_start();
+
+            let value;
+            (value, rest) = match after.strip_prefix('"') {
+                // escaped quotes aren't supported, they can't be in any of the values we check
+                Some(quoted) => {
+                    let (value, rest) = quoted.split_once('"')?;
+                    if value.contains('\\') {
+                        return None;
+                    }
+                    (value, rest)
+                }
+                None => after
+                    .split_once(',')
+                    .map_or((after, ""), |(value, rest)| (value, rest)),
+            };
+            rest = rest.trim_start().trim_start_matches(',').trim_start();
+
+            match name.trim() {
+                "username" => username = Some(value),
+                "realm" => realm = Some(value),
+                "uri" => uri = Some(value),
+                "nonce" => nonce = Some(value),
+                "nc" => nc = Some(value.trim()),
+                "cnonce" => cnonce = Some(value),
+                "response" => response = Some(value),
+                "qop" => qop = Some(value.trim()),
+                "algorithm" => {
+                    algorithm = match value.trim() {
+                        a if a.eq_ignore_ascii_case("MD5") => Algorithm::Md5,
+                        a if a.eq_ignore_ascii_case("SHA-256") => Algorithm::Sha256,
+                        _ => return None,
+                    }
+                }
+                // hashed usernames aren't supported
+                "userhash" if !value.trim().eq_ignore_ascii_case("false") => return None,
+                _ => {}
+            }
+        }
+
+        let nc = nc?;
+        if !qop?.eq_ignore_ascii_case("auth") || nc.len() != 8 {
+            return None;
+        }
+
+        Some(Self {
+            username: username?,
+            realm: realm?,
+            uri: uri?,
+            algorithm,
+            nonce: nonce?,
+            nc,
+            cnonce: cnonce?,
+            response: response?,
+        })
+    }
+}
+
+impl DigestParams<'_> {
+    /// Computes the response sent by a client that knows the password, from the hex encoded `ha1` of the user.
+    fn expected_response<'o>(
+        &self,
+        method: HttpMethod,
+        ha1: &[u8],
+        out: &'o mut [u8; MAX_HEX_LEN],
+    ) -> &'o [u8] {
+        let mut ha2 = [0u8; MAX_HEX_LEN];
+        let ha2 = self
+            .algorithm
+            .hex_digest(&[method.as_str().as_bytes(), self.uri.as_bytes()], &mut ha2);
+
+        self.algorithm.hex_digest(
+            &[
+                ha1,
+                self.nonce.as_bytes(),
+                self.nc.as_bytes(),
+                self.cnonce.as_bytes(),
+                b"auth",
+                ha2,
+            ],
+            out,
+        )
+    }
+}
+
+/// Checks whether `uri` is the target of the request.
+fn matches_target(request: &HttpRequest, uri: &str) -> bool {
+    let Some(rest) = uri.strip_prefix(request.path()) else {
+        return false;
+    };
+
+    match request.query() {
+        Some(query) => rest.strip_prefix('?') == Some(query),
+        None => rest.is_empty(),
+    }
+}
+
+fn decode_nonce(hex: &str) -> Option<[u8; NONCE_LEN]> {
+    if hex.len() != NONCE_LEN * 2 {
+        return None;
+    }
+
+    let mut nonce = [0u8; NONCE_LEN];
+    for (byte, pair) in nonce.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = core::str::from_utf8(pair).ok()?;
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+
+    Some(nonce)
+}
+
+/// Writes `data` as lowercase hex to `out`, which has to be at least twice as long.
+fn encode_hex<'o>(data: &[u8], out: &'o mut [u8]) -> &'o str {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    for (byte, pair) in data.iter().zip(out.chunks_exact_mut(2)) {
+        pair[0] = DIGITS[(byte >> 4) as usize];
+        pair[1] = DIGITS[(byte & 0xf) as usize];
+    }
+
+    let len = (data.len() * 2).min(out.len());
+    // only ASCII digits were written
+    core::str::from_utf8(&out[..len]).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The example of RFC 7616 section 3.9.1, with the password "Circle of Life".
+    const EXAMPLE: &str = "Digest username=\"Mufasa\", realm=\"http-auth@example.org\", uri=\"/dir/index.html\", \
+        algorithm=MD5, nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", nc=00000001, \
+        cnonce=\"f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ\", qop=auth, \
+        response=\"8ca523f5e9506fed4657c9700eebdbec\", opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"";
+
+    #[test]
+    fn params() {
+        let params = DigestParams::parse(EXAMPLE).unwrap();
+        assert_eq!(params.username, "Mufasa");
+        assert_eq!(params.realm, "http-auth@example.org");
+        assert_eq!(params.uri, "/dir/index.html");
+        assert_eq!(params.algorithm, Algorithm::Md5);
+        assert_eq!(params.nonce, "7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v");
+        assert_eq!(params.nc, "00000001");
+        assert_eq!(
+            params.cnonce,
+            "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ"
+        );
+        assert_eq!(params.response, "8ca523f5e9506fed4657c9700eebdbec");
+
+        // quotes are optional, and the values can be in any order
+        let params = DigestParams::parse(
+            "digest qop=\"auth\",algorithm=sha-256, username=u,realm=r, uri=/, nonce=n, nc=0000000a, cnonce=c, response=R, userhash=false",
+        )
+        .unwrap();
+        assert_eq!(params.algorithm, Algorithm::Sha256);
+        assert_eq!(params.username, "u");
+        assert_eq!(params.uri, "/");
+        assert_eq!(params.nc, "0000000a");
+        assert_eq!(params.response, "R");
+
+        // the algorithm defaults to MD5
+        let params =
+            DigestParams::parse("Digest username=u, realm=r, uri=/, nonce=n, nc=00000001, cnonce=c, qop=auth, response=R")
+                .unwrap();
+        assert_eq!(params.algorithm, Algorithm::Md5);
+    }
+
+    #[test]
+    fn invalid_params() {
+        for header in [
+            // RFC 2069 clients, without qop
+            "Digest username=u, realm=r, uri=/, nonce=n, nc=00000001, cnonce=c, response=R",
+            "Digest username=u, realm=r, uri=/, nonce=n, nc=00000001, cnonce=c, qop=auth-int, response=R",
+            "Digest username=u, realm=r, uri=/, nonce=n, nc=1, cnonce=c, qop=auth, response=R",
+            "Digest username=u, realm=r, uri=/, nonce=n, nc=000000001, cnonce=c, qop=auth, response=R",
+            "Digest username=u, realm=r, uri=/, nonce=n, nc=00000001, qop=auth, response=R",
+            "Digest username=u, realm=r, uri=/, nonce=n, nc=00000001, cnonce=c, qop=auth, response=R, userhash=true",
+            "Digest username=u, realm=r, uri=/, nonce=n, nc=00000001, cnonce=c, qop=auth, response=R, algorithm=SHA-512",
+            "Digest username=\"u\\\"\", realm=r, uri=/, nonce=n, nc=00000001, cnonce=c, qop=auth, response=R",
+            "Digest username=\"u, realm=r, uri=/, nonce=n, nc=00000001, cnonce=c, qop=auth, response=R",
+            "Basic dTpw",
+        ] {
+            assert!(DigestParams::parse(header).is_none(), "{header}");
+        }
+    }
+
+    #[test]
+    fn responses() {
+        let credentials: &[(&str, &str)] = &[("admin", "hunter2"), ("Mufasa", "Circle of Life")];
+
+        let mut params = DigestParams::parse(EXAMPLE).unwrap();
+        for (algorithm, response) in [
+            (Algorithm::Md5, "8ca523f5e9506fed4657c9700eebdbec"),
+            (
+                Algorithm::Sha256,
+                "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1",
+            ),
+        ] {
+            params.algorithm = algorithm;
+
+            let mut ha1 = [0u8; MAX_HEX_LEN];
+            let user = embassy_futures::block_on(credentials.ha1(
+                params.username,
+                params.realm,
+                algorithm,
+                &mut ha1,
+            ));
+            assert_eq!(user, Some("Mufasa"));
+
+            let mut expected = [0u8; MAX_HEX_LEN];
+            let expected = params.expected_response(
+                HttpMethod::Get,
+                &ha1[..algorithm.hex_len()],
+                &mut expected,
+            );
+            assert_eq!(expected, response.as_bytes());
+        }
+
+        let mut ha1 = [0u8; MAX_HEX_LEN];
+        let user = embassy_futures::block_on(credentials.ha1(
+            "mufasa",
+            "http-auth@example.org",
+            Algorithm::Md5,
+            &mut ha1,
+        ));
+        assert_eq!(user, None);
+    }
+
+    /// Fills nonces with the same byte, incremented every time.
+    struct Counter(u8);
+
+    impl RngCore for Counter {
+        fn next_u32(&mut self) -> u32 {
+            unimplemented!()
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            unimplemented!()
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            self.0 += 1;
+            dest.fill(self.0);
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn nonce_counts() {
+        let mut nonces = Nonces::<_, 2> {
+            rng: Counter(0),
+            table: [None; 2],
+            next: 0,
+        };
+
+        let first = nonces.issue();
+        assert_eq!(first, [1; NONCE_LEN]);
+        assert_eq!(nonces.use_count(&first, 1), Some(true));
+        // replayed
+        assert_eq!(nonces.use_count(&first, 1), Some(false));
+        assert_eq!(nonces.use_count(&first, 3), Some(true));
+        // out of order
+        assert_eq!(nonces.use_count(&first, 2), Some(false));
+
+        let second = nonces.issue();
+        assert_eq!(nonces.use_count(&second, 1), Some(true));
+        assert_eq!(nonces.use_count(&first, 4), Some(true));
+
+        // the oldest nonce is evicted
+        nonces.issue();
+        assert_eq!(nonces.use_count(&first, 5), None);
+        assert_eq!(nonces.use_count(&second, 2), Some(true));
+        assert_eq!(nonces.use_count(&[0; NONCE_LEN], 1), None);
+    }
+
+    #[test]
+    fn nonces() {
+        let mut hex = [0u8; NONCE_LEN * 2];
+        let nonce = [0xab; NONCE_LEN];
+        let encoded = encode_hex(&nonce, &mut hex);
+        assert_eq!(encoded, "abababababababababababababababab");
+        assert_eq!(decode_nonce(encoded), Some(nonce));
+
+        assert_eq!(decode_nonce("ab"), None);
+        assert_eq!(decode_nonce("zbababababababababababababababab"), None);
+    }
+}
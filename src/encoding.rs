@@ -0,0 +1,171 @@
+use crate::{headers::HeaderName, request::HttpRequest};
+
+/// A content coding of a response body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ContentCoding {
+    Identity,
+    Gzip,
+    Br,
+}
+
+impl ContentCoding {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            ContentCoding::Identity => "identity",
+            ContentCoding::Gzip => "gzip",
+            ContentCoding::Br => "br",
+        }
+    }
+}
+
+/// The content codings accepted by a client, with their quality values in thousandths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AcceptEncoding {
+    identity: u16,
+    gzip: u16,
+    br: u16,
+}
+
+impl Default for AcceptEncoding {
+    /// Only the identity coding, for clients that don't send `Accept-Encoding`.
+    fn default() -> Self {
+        Self {
+            identity: 1000,
+            gzip: 0,
+            br: 0,
+        }
+    }
+}
+
+impl AcceptEncoding {
+    pub(crate) fn from_request(request: &HttpRequest) -> Self {
+        match request.try_find_header(&HeaderName::AcceptEncoding) {
+            Some(header) => Self::parse(header),
+            None => Self::default(),
+        }
+    }
+
+    /// Parses the value of an `Accept-Encoding` header, like `br;q=1.0, gzip;q=0.8, *;q=0.1`.
+    pub(crate) fn parse(header: &str) -> Self {
+        let (mut identity, mut gzip, mut br, mut any) = (None, None, None, None);
+
+        for coding in header.split(',') {
+            let mut params = coding.split(';');
+            let name = params.next().unwrap_or_default().trim();
+
+            let mut q = Some(1000);
+            for param in params {
+                if let Some((key, value)) = param.split_once('=') {
+                    if key.trim().eq_ignore_ascii_case("q") {
+                        q = parse_q(value.trim());
+                    }
+                }
+            }
+            // codings with an invalid quality value are ignored
+            let Some(q) = q else {
+                continue;
+            };
+
+            if name.eq_ignore_ascii_case("identity") {
+                identity = Some(q);
+            } else if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+                gzip = Some(q);
+            } else if name.eq_ignore_ascii_case("br") {
+                br = Some(q);
+            } else if name == "*" {
+                any = Some(q);
+            }
+        }
+
+        Self {
+            // identity is acceptable unless explicitly refused, but the listed codings are preferred
+            identity: identity.or(any).unwrap_or(1),
+            gzip: gzip.or(any).unwrap_or(0),
+            br: br.or(any).unwrap_or(0),
+        }
+    }
+
+    /// Picks the coding with the highest quality value among the available ones,
+    /// preferring the smallest ones on ties. Falls back to identity if none is acceptable.
+    pub(crate) fn choose(&self, gzip: bool, br: bool) -> ContentCoding {
+        let candidates = [
+            (ContentCoding::Br, br, self.br),
+            (ContentCoding::Gzip, gzip, self.gzip),
+            (ContentCoding::Identity, true, self.identity),
+        ];
+
+        let mut best = (ContentCoding::Identity, 0);
+        for (coding, available, q) in candidates {
+            if available && q > best.1 {
+                best = (coding, q);
+            }
+        }
+
+        best.0
+    }
+}
+
+/// Parses a quality value like `0.8` into thousandths.
+fn parse_q(value: &str) -> Option<u16> {
+    let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut q = match int {
+        "0" => 0,
+        "1" => 1000,
+        _ => return None,
+    };
+    for (digit, scale) in frac.bytes().zip([100, 10, 1]) {
+        q += (digit - b'0') as u16 * scale;
+    }
+
+    (q <= 1000).then_some(q)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quality_values() {
+        assert_eq!(parse_q("1"), Some(1000));
+        assert_eq!(parse_q("1.000"), Some(1000));
+        assert_eq!(parse_q("0"), Some(0));
+        assert_eq!(parse_q("0.8"), Some(800));
+        assert_eq!(parse_q("0.05"), Some(50));
+        assert_eq!(parse_q("0.123"), Some(123));
+        assert_eq!(parse_q("0."), Some(0));
+    }
+
+    #[test]
+    fn invalid_quality_values() {
+        assert_eq!(parse_q("1.001"), None);
+        assert_eq!(parse_q("1.5"), None);
+        assert_eq!(parse_q("2"), None);
+        assert_eq!(parse_q("0.1234"), None);
+        assert_eq!(parse_q("0.-1"), None);
+        assert_eq!(parse_q(".5"), None);
+        assert_eq!(parse_q(""), None);
+    }
+
+    #[test]
+    fn choose() {
+        let accept = AcceptEncoding::parse("gzip;q=0.5, br;q=0.5");
+        assert_eq!(accept.choose(true, true), ContentCoding::Br);
+        assert_eq!(accept.choose(true, false), ContentCoding::Gzip);
+        assert_eq!(accept.choose(false, false), ContentCoding::Identity);
+
+        // codings with an invalid quality value are ignored
+        let accept = AcceptEncoding::parse("br;q=2, gzip");
+        assert_eq!(accept.choose(true, true), ContentCoding::Gzip);
+
+        let accept = AcceptEncoding::parse("*;q=0");
+        assert_eq!(accept.choose(true, true), ContentCoding::Identity);
+        assert_eq!(
+            AcceptEncoding::default().choose(true, true),
+            ContentCoding::Identity
+        );
+    }
+}
@@ -1,7 +1,7 @@
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
-    /// TCP error. Can't return any error page
-    Tcp(embassy_net::tcp::Error),
+    /// I/O error on the connection. Can't return any error page
+    Io(embedded_io_async::ErrorKind),
     /// HTTP range request out of range. HTTP 416 Range Not Satisfiable
     OutOfRange,
 
@@ -13,10 +13,16 @@ pub enum Error {
 
     /// The client sent a request with too many headers or a body too large. HTTP 413 Entity Too Large
     EntityTooLarge,
+
+    /// The request headers don't fit in the HTTP buffer. HTTP 431 Request Header Fields Too Large
+    HeadersTooLarge,
+
+    /// The client took too long to send the request headers. HTTP 408 Request Timeout
+    Timeout,
 }
 
-impl From<embassy_net::tcp::Error> for Error {
-    fn from(value: embassy_net::tcp::Error) -> Self {
-        Error::Tcp(value)
+impl<E: embedded_io_async::Error> From<E> for Error {
+    fn from(value: E) -> Self {
+        Error::Io(value.kind())
     }
 }
@@ -0,0 +1,137 @@
+//! Static files embedded in flash, like a web UI.
+//!
+//! With the `embed` feature, a whole directory can be embedded at compile time with [`embed_dir!`],
+//! and served by [`crate::router!`] for every path that doesn't match a route:
+//!
+//! ```ignore
+//! static WEB_UI: StaticDir = embed_dir!("static");
+//!
+//! router! {
+//!     GET "/api/led" => get_led,
+//!     _ => WEB_UI,
+//! }
+//! ```
+
+#[cfg(feature = "embed")]
+pub use tinyhttp_macros::embed_dir;
+
+use crate::{
+    config::StaticPage,
+    error::Error,
+    request::HttpRequest,
+    status::StatusCode,
+    transport::Transport,
+    writer::{HttpResponse, ResponseWriter},
+};
+
+/// Maximum length of the decoded path of a static file.
+pub const MAX_PATH_LEN: usize = 256;
+
+/// A file served at a fixed path.
+#[derive(Debug, Clone, Copy)]
+pub struct StaticFile<'a> {
+    /// The path of the file, starting with a `/`.
+    pub path: &'a str,
+    pub page: StaticPage<'a>,
+}
+
+impl<'a> StaticFile<'a> {
+    /// Length of the file, in bytes.
+    pub fn len(&self) -> usize {
+        self.page.body.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.page.body.is_empty()
+    }
+
+    /// Sends the file as the response.
+    pub async fn send<T: Transport>(
+        &self,
+        writer: ResponseWriter<'a, '_, T>,
+    ) -> Result<HttpResponse, Error> {
+        writer.static_page(self.page, StatusCode::OK).await
+    }
+}
+
+/// A directory of static files, sorted by path.
+#[derive(Debug, Clone, Copy)]
+pub struct StaticDir<'a> {
+    files: &'a [StaticFile<'a>],
+    /// Picks the `Cache-Control` header of the files that don't have one, from their path.
+    cache_control: fn(&str) -> Option<&'a str>,
+}
+
+impl<'a> StaticDir<'a> {
+    /// Creates a directory from its files, which must be sorted by path.
+    pub const fn new(files: &'a [StaticFile<'a>]) -> Self {
+        Self {
+            files,
+            cache_control: |_| None,
+        }
+    }
+
+    /// Sets the `Cache-Control` header of the files from their path, like:
+    ///
+    /// ```ignore
+    /// fn cache_control(path: &str) -> Option<&'static str> {
+    ///     if path.starts_with("/assets/") {
+    ///         // the names of the assets change with their content
+    ///         Some("max-age=31536000, immutable")
+    ///     } else {
+    ///         Some("no-cache")
+    ///     }
+    /// }
+    ///
+    /// static WEB_UI: StaticDir = embed_dir!("static").with_cache_control(cache_control);
+    /// ```
+    ///
+    /// It's not used for files whose page already has one.
+    pub const fn with_cache_control(mut self, cache_control: fn(&str) -> Option<&'a str>) -> Self {
+        self.cache_control = cache_control;
+        self
+    }
+
+    pub fn files(&self) -> &'a [StaticFile<'a>] {
+        self.files
+    }
+
+    /// Finds the file at `path`.
+    pub fn find(&self, path: &str) -> Option<&'a StaticFile<'a>> {
+        self.files
+            .binary_search_by(|file| file.path.cmp(path))
+            .ok()
+            .map(|i| &self.files[i])
+    }
+
+    /// Finds the file asked by a request. Paths ending with a `/` are mapped to their `index.html`.
+    pub fn find_request(&self, request: &HttpRequest) -> Option<&'a StaticFile<'a>> {
+        const INDEX: &str = "index.html";
+
+        let mut buf = [0u8; MAX_PATH_LEN + INDEX.len()];
+        let len = request.decoded_path(&mut buf[..MAX_PATH_LEN]).ok()?.len();
+
+        let len = if buf[..len].ends_with(b"/") {
+            buf[len..len + INDEX.len()].copy_from_slice(INDEX.as_bytes());
+            len + INDEX.len()
+        } else {
+            len
+        };
+
+        self.find(core::str::from_utf8(&buf[..len]).ok()?)
+    }
+
+    /// Sends one of the files of the directory as the response, with its `Cache-Control` header.
+    pub async fn send<T: Transport>(
+        &self,
+        file: &StaticFile<'a>,
+        writer: ResponseWriter<'a, '_, T>,
+    ) -> Result<HttpResponse, Error> {
+        let mut page = file.page;
+        if page.cache_control.is_none() {
+            page.cache_control = (self.cache_control)(file.path);
+        }
+
+        writer.static_page(page, StatusCode::OK).await
+    }
+}
@@ -10,7 +10,16 @@ const CONTENT_LENGTH: UniCase<&str> = UniCase::ascii("Content-Length");
 const CONTENT_TYPE: UniCase<&str> = UniCase::ascii("Content-Type");
 const COOKIE: UniCase<&str> = UniCase::ascii("Cookie");
 const DATE: UniCase<&str> = UniCase::ascii("Date");
+const ORIGIN: UniCase<&str> = UniCase::ascii("Origin");
+const ACCESS_CONTROL_REQUEST_METHOD: UniCase<&str> =
+    UniCase::ascii("Access-Control-Request-Method");
+const ACCESS_CONTROL_REQUEST_HEADERS: UniCase<&str> =
+    UniCase::ascii("Access-Control-Request-Headers");
+const IF_MATCH: UniCase<&str> = UniCase::ascii("If-Match");
+const IF_NONE_MATCH: UniCase<&str> = UniCase::ascii("If-None-Match");
+const IF_RANGE: UniCase<&str> = UniCase::ascii("If-Range");
 const RANGE: UniCase<&str> = UniCase::ascii("Range");
+const TRANSFER_ENCODING: UniCase<&str> = UniCase::ascii("Transfer-Encoding");
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum HeaderName<'a> {
@@ -24,7 +33,14 @@ pub enum HeaderName<'a> {
     ContentType,
     Cookie,
     Date,
+    Origin,
+    AccessControlRequestMethod,
+    AccessControlRequestHeaders,
+    IfMatch,
+    IfNoneMatch,
+    IfRange,
     Range,
+    TransferEncoding,
     Other(&'a str),
 }
 
@@ -53,8 +69,22 @@ impl<'a> HeaderName<'a> {
             Self::Cookie
         } else if case == DATE {
             Self::Date
+        } else if case == ORIGIN {
+            Self::Origin
+        } else if case == ACCESS_CONTROL_REQUEST_METHOD {
+            Self::AccessControlRequestMethod
+        } else if case == ACCESS_CONTROL_REQUEST_HEADERS {
+            Self::AccessControlRequestHeaders
+        } else if case == IF_MATCH {
+            Self::IfMatch
+        } else if case == IF_NONE_MATCH {
+            Self::IfNoneMatch
+        } else if case == IF_RANGE {
+            Self::IfRange
         } else if case == RANGE {
             Self::Range
+        } else if case == TRANSFER_ENCODING {
+            Self::TransferEncoding
         } else {
             Self::Other(s)
         }
@@ -1,13 +1,24 @@
 #![no_std]
 #![feature(async_fn_traits)]
 
+#[cfg(feature = "http_basic_auth")]
+pub mod auth;
 pub mod config;
+#[cfg(feature = "digest_auth")]
+pub mod digest_auth;
+mod encoding;
 pub mod error;
+pub mod files;
 mod headers;
+pub mod middleware;
 pub mod reader;
-mod request;
+pub mod request;
 pub mod routing;
 pub mod status;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transport;
+pub mod url;
 mod utils;
 pub mod writer;
 
@@ -19,33 +30,98 @@ pub mod logging;
 
 use core::ops::AsyncFn;
 
-use config::HttpConfig;
-use embassy_net::tcp::TcpSocket;
+use config::{HttpConfig, StaticPage};
+use embassy_futures::join::join_array;
+use embedded_io_async::Write;
 use error::Error;
-use reader::{HttpReader, RequestReader};
+use middleware::{Middleware, Router};
+use reader::{BodyState, HttpReader, RequestReader};
+use request::HttpMethod;
 use status::StatusCode;
+use transport::{Connection, Listener, TcpListener};
 use writer::{HttpResponse, ResponseWriter};
 
 #[cfg(not(any(feature = "ipv4", feature = "ipv6")))]
 compile_error!("You must select at least one of the following features: 'ipv4', 'ipv6'");
 
+/// Buffers used by a single TCP connection.
+pub struct ConnectionBuffers<const TX: usize, const RX: usize, const HTTP: usize> {
+    tx_buf: [u8; TX],
+    rx_buf: [u8; RX],
+    http_buf: [u8; HTTP],
+}
+
+impl<const TX: usize, const RX: usize, const HTTP: usize> ConnectionBuffers<TX, RX, HTTP> {
+    pub const fn new() -> Self {
+        Self {
+            tx_buf: [0u8; TX],
+            rx_buf: [0u8; RX],
+            http_buf: [0u8; HTTP],
+        }
+    }
+}
+
+impl<const TX: usize, const RX: usize, const HTTP: usize> Default
+    for ConnectionBuffers<TX, RX, HTTP>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Memory needed to serve up to `N` clients concurrently with [`RoutableHttpServer::run_pool`].
+///
+/// `TX`, `RX` and `HTTP` are the sizes of the buffers of each connection,
+/// see [`RoutableHttpServer::run`] for the recommended values.
+///
+/// This can get quite big, so you may want to put it in a `static`.
+pub struct ServerResources<
+    const N: usize,
+    const TX: usize = 1024,
+    const RX: usize = 1024,
+    const HTTP: usize = 2048,
+> {
+    connections: [ConnectionBuffers<TX, RX, HTTP>; N],
+}
+
+impl<const N: usize, const TX: usize, const RX: usize, const HTTP: usize>
+    ServerResources<N, TX, RX, HTTP>
+{
+    pub const fn new() -> Self {
+        Self {
+            connections: [const { ConnectionBuffers::new() }; N],
+        }
+    }
+}
+
+impl<const N: usize, const TX: usize, const RX: usize, const HTTP: usize> Default
+    for ServerResources<N, TX, RX, HTTP>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// HTTP server without any routes associated with it
-pub struct HttpServer<'a> {
-    network_stack: embassy_net::Stack<'a>,
+///
+/// `N` is the network stack it listens on. It's `()` when the server is only run with [`RoutableHttpServer::run_on`].
+///
+/// `S` is the application state shared with every handler, see [`HttpServer::with_state`].
+pub struct HttpServer<'a, N = embassy_net::Stack<'a>, S: ?Sized = ()> {
+    network_stack: N,
     config: &'a HttpConfig<'a>,
+    state: &'a S,
 }
 
-pub struct RoutableHttpServer<'a, F>
-where
-    F: for<'c, 'd, 'e> AsyncFn(
-        &'c HttpConfig<'d>,
-        RequestReader<'c, 'd, 'e>,
-        ResponseWriter<'c, 'd>,
-    ) -> Result<HttpResponse, Error>,
-{
-    network_stack: embassy_net::Stack<'a>,
+/// HTTP server with a router, and the middlewares that run before it.
+///
+/// `M` is the chain of middlewares, see [`RoutableHttpServer::layer`].
+pub struct RoutableHttpServer<'a, F, N = embassy_net::Stack<'a>, S: ?Sized = (), M = ()> {
+    network_stack: N,
     config: &'a HttpConfig<'a>,
+    state: &'a S,
     router: F,
+    middleware: M,
 }
 
 impl<'a> HttpServer<'a> {
@@ -53,37 +129,60 @@ impl<'a> HttpServer<'a> {
         Self {
             network_stack,
             config,
+            state: &(),
+        }
+    }
+}
+
+impl<'a> HttpServer<'a, ()> {
+    /// Creates a server without a network stack, to run on a custom [`Listener`].
+    pub fn with_config(config: &'a HttpConfig) -> Self {
+        Self {
+            network_stack: (),
+            config,
+            state: &(),
+        }
+    }
+}
+
+impl<'a, N> HttpServer<'a, N> {
+    /// Shares `state` with every handler, like sensor readings or settings.
+    ///
+    /// Use `state: Type;` at the start of the [router!] macro to pass it to the handlers.
+    pub fn with_state<S: ?Sized>(self, state: &'a S) -> HttpServer<'a, N, S> {
+        HttpServer {
+            network_stack: self.network_stack,
+            config: self.config,
+            state,
         }
     }
+}
 
+impl<'a, N, S: ?Sized> HttpServer<'a, N, S> {
     /// Adds routing information to this [HttpServer]
     ///
     /// Use the [router!] macro to specify your routes
-    pub fn route<F>(self, f: F) -> RoutableHttpServer<'a, F>
-    where
-        F: for<'c, 'd, 'e> AsyncFn(
-            &'c HttpConfig<'d>,
-            RequestReader<'c, 'd, 'e>,
-            ResponseWriter<'c, 'd>,
-        ) -> Result<HttpResponse, Error>,
-    {
+    pub fn route<F>(self, f: F) -> RoutableHttpServer<'a, F, N, S> {
         RoutableHttpServer {
             network_stack: self.network_stack,
             config: self.config,
+            state: self.state,
             router: f,
+            middleware: (),
         }
     }
 }
 
-impl<'a, F> RoutableHttpServer<'a, F>
+impl<'a, F, S: ?Sized, M: Middleware<S>> RoutableHttpServer<'a, F, embassy_net::Stack<'a>, S, M>
 where
     F: for<'c, 'd, 'e> AsyncFn(
         &'c HttpConfig<'d>,
+        &'c S,
         RequestReader<'c, 'd, 'e>,
         ResponseWriter<'c, 'd>,
     ) -> Result<HttpResponse, Error>,
 {
-    /// Runs the HTTP server.
+    /// Runs the HTTP server, serving one client at a time.
     /// Recommended buffer sizes:
     ///
     /// `tx_buf` >=`1024`
@@ -92,66 +191,214 @@ where
     ///
     /// `http_buf` >=`2048`
     pub async fn run(&mut self, tx_buf: &mut [u8], rx_buf: &mut [u8], http_buf: &mut [u8]) {
+        let listener = self.listener(tx_buf, rx_buf);
+
+        self.serve(listener, http_buf).await
+    }
+
+    /// Runs the HTTP server, serving up to `N` clients concurrently.
+    ///
+    /// Every connection uses its own set of buffers from `resources`, and all of them share the same router.
+    ///
+    /// The network stack must have at least `N` free sockets.
+    pub async fn run_pool<const N: usize, const TX: usize, const RX: usize, const HTTP: usize>(
+        &mut self,
+        resources: &mut ServerResources<N, TX, RX, HTTP>,
+    ) {
+        let server = &*self;
+
+        let connections = resources.connections.each_mut().map(|c| {
+            let listener = server.listener(&mut c.tx_buf, &mut c.rx_buf);
+
+            server.serve(listener, &mut c.http_buf)
+        });
+
+        join_array(connections).await;
+    }
+
+    /// Creates a TCP listener on the configured port.
+    fn listener<'b>(&self, tx_buf: &'b mut [u8], rx_buf: &'b mut [u8]) -> TcpListener<'b>
+    where
+        'a: 'b,
+    {
+        // set the timeout to the configured value, or if none, set it to the default, and then handle closing the socket separately
+        TcpListener::new(
+            self.network_stack,
+            self.config.port,
+            embassy_time::Duration::from_secs(self.config.keepalive.unwrap_or(5) as u64),
+            rx_buf,
+            tx_buf,
+        )
+    }
+}
+
+impl<'a, F, N, S: ?Sized, M> RoutableHttpServer<'a, F, N, S, M> {
+    /// Adds a middleware, that runs before the router for every request.
+    ///
+    /// Middlewares run in the order they were added, see [`middleware`] for how to write one.
+    pub fn layer<L: Middleware<S>>(self, middleware: L) -> RoutableHttpServer<'a, F, N, S, (M, L)> {
+        RoutableHttpServer {
+            network_stack: self.network_stack,
+            config: self.config,
+            state: self.state,
+            router: self.router,
+            middleware: (self.middleware, middleware),
+        }
+    }
+}
+
+impl<'a, F, N, S: ?Sized, M: Middleware<S>> RoutableHttpServer<'a, F, N, S, M> {
+    /// Runs the HTTP server on a custom [`Listener`], serving one client at a time.
+    ///
+    /// See [`RoutableHttpServer::run`] for the recommended size of `http_buf`.
+    pub async fn run_on<L: Listener>(&mut self, listener: L, http_buf: &mut [u8])
+    where
+        F: for<'c, 'd, 'e> AsyncFn(
+            &'c HttpConfig<'d>,
+            &'c S,
+            RequestReader<'c, 'd, 'e, L::Transport>,
+            ResponseWriter<'c, 'd, L::Transport>,
+        ) -> Result<HttpResponse, Error>,
+    {
+        self.serve(listener, http_buf).await
+    }
+
+    /// Gets the status code (and page, if configured) to send when a request fails with `error`.
+    fn error_page(&self, error: Error) -> (Option<StaticPage<'a>>, StatusCode) {
+        match error {
+            Error::BadRequest => (self.config.http_400, StatusCode::BAD_REQUEST),
+            Error::EntityTooLarge => (None, StatusCode::PAYLOAD_TOO_LARGE),
+            Error::OutOfRange => (None, StatusCode::RANGE_NOT_SATISFIABLE),
+            Error::HeadersTooLarge => (None, StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE),
+            Error::Timeout => (None, StatusCode::REQUEST_TIMEOUT),
+            Error::Io(_) | Error::EOF => (self.config.http_500, StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    /// Accepts and handles connections one after the other, forever.
+    async fn serve<L: Listener>(&self, mut listener: L, http_buf: &mut [u8])
+    where
+        F: for<'c, 'd, 'e> AsyncFn(
+            &'c HttpConfig<'d>,
+            &'c S,
+            RequestReader<'c, 'd, 'e, L::Transport>,
+            ResponseWriter<'c, 'd, L::Transport>,
+        ) -> Result<HttpResponse, Error>,
+    {
         loop {
-            let mut socket = TcpSocket::new(self.network_stack, rx_buf, tx_buf);
+            let mut connection = match listener.accept().await {
+                Ok(c) => c,
+                Err(_) => {
+                    log!(error, "Error while accepting connection");
 
-            // set the timeout to the configured value, or if none, set it to the default, and then handle closing the socket separately
-            socket.set_timeout(Some(embassy_time::Duration::from_secs(
-                self.config.keepalive.unwrap_or(5) as u64,
-            )));
+                    continue;
+                }
+            };
 
-            if (socket.accept(self.config.port).await).is_err() {
-                log!(error, "Error while accepting socket");
+            self.handle_connection(&mut connection, http_buf).await;
+        }
+    }
 
-                continue;
-            }
+    /// Handles requests from a client until the connection is closed.
+    async fn handle_connection<C: Connection>(&self, connection: &mut C, http_buf: &mut [u8])
+    where
+        F: for<'c, 'd, 'e> AsyncFn(
+            &'c HttpConfig<'d>,
+            &'c S,
+            RequestReader<'c, 'd, 'e, C::Transport>,
+            ResponseWriter<'c, 'd, C::Transport>,
+        ) -> Result<HttpResponse, Error>,
+    {
+        let idle_timeout =
+            embassy_time::Duration::from_secs(self.config.keepalive.unwrap_or(5) as u64);
+        let header_timeout = embassy_time::Duration::from_secs(self.config.header_timeout as u64);
+
+        // whether the connection has to be aborted instead of closed gracefully
+        let abort = {
+            let (mut rx, mut tx) = connection.split();
+
+            // data of the next request that was received together with the previous one
+            let mut filled = 0usize;
+            let mut body = BodyState::default();
 
             loop {
-                let (mut reader, mut writer) = socket.split();
                 // wait for HTTP request
-                let reader = match HttpReader::try_new(&mut reader, http_buf).await {
-                    Ok(r) => r,
-                    Err(Error::Tcp(_)) => {
-                        log!(error, "TCP error while parsing HTTP request.");
-
-                        socket.abort();
-                        _ = socket.flush().await;
-                        break;
+                let received = HttpReader::<C::Transport>::receive(
+                    &mut rx,
+                    http_buf,
+                    filled,
+                    idle_timeout,
+                    header_timeout,
+                )
+                .await;
+                let (request, buf) = match received.and_then(|(header_end, total)| {
+                    let buf = &http_buf[..total];
+                    let request = HttpReader::<C::Transport>::parse(buf, header_end, &mut body)?;
+
+                    Ok((request, buf))
+                }) {
+                    Ok(received) => received,
+                    Err(Error::Io(_)) => {
+                        log!(error, "I/O error while parsing HTTP request.");
+
+                        break true;
                     }
                     Err(Error::EOF) => {
-                        socket.close();
-                        _ = socket.flush().await;
-                        break;
+                        break false;
                     }
-                    _ => {
-                        log!(debug, "Error while parsing HTTP request, sending HTTP 400.");
+                    Err(e) => {
+                        log!(
+                            debug,
+                            "Error while parsing HTTP request, sending HTTP error page."
+                        );
 
-                        // send 400
-                        let writer = ResponseWriter::new_http_11(&mut writer);
+                        // the request line may still tell whether it's a HEAD request
+                        let method = HttpMethod::from_request_line(http_buf);
 
-                        let _ = writer
-                            .static_page_or_empty(self.config.http_400, StatusCode::BAD_REQUEST)
-                            .await;
+                        let (page, code) = self.error_page(e);
+                        let writer = ResponseWriter::<C::Transport>::new_http_11(&mut tx, method);
 
-                        socket.close();
-                        _ = socket.flush().await;
-                        break;
+                        let _ = writer.static_page_or_empty(page, code).await;
+
+                        break false;
                     }
                 };
+                // keep the connection open only if both the client and the configuration allow it
+                let keep_alive = self.config.keepalive.is_some() && request.keep_alive();
+
+                // the reader and the writer both borrow the request, the reader is given to the handler
+                let reader = HttpReader::<C::Transport>::new(&mut rx, &request, buf, &mut body);
+
                 // create writer so the handler can write out an HTTP response
-                let writer = ResponseWriter::new(&mut writer, &reader);
+                let mut started = false;
+                let writer = ResponseWriter::<C::Transport>::new(
+                    &mut tx,
+                    &request,
+                    keep_alive,
+                    self.config.cors.as_ref(),
+                    &mut started,
+                );
 
                 // if global http basic auth is enabled, check for authentication
                 // if not, this is always true at compile time
                 let result = if routing::global_basic_auth!(self.config, reader) {
-                    // if a handler exists for this request, use it, otherwise send a 404
-                    self.router.async_call((self.config, reader, writer)).await
+                    // run the middlewares, and then the handler for this request if one exists, otherwise send a 404
+                    self.middleware
+                        .handle(
+                            self.config,
+                            self.state,
+                            reader,
+                            writer,
+                            &Router(&self.router),
+                        )
+                        .await
                 } else {
                     log!(
                         debug,
                         "Asking for authentication to access page {}",
                         reader.request.path()
                     );
+
                     writer::static_or_empty_page!(
                         writer,
                         self.config.http_401,
@@ -160,34 +407,72 @@ where
                     )
                 };
 
+                // release the HTTP buffer, so the body can be discarded later
+                let method = request.method();
+                drop(request);
+
                 // flush and map the error
                 let result = match result {
-                    Ok(r) => socket.flush().await.map(|_| r).map_err(|e| e.into()),
+                    Ok(r) => tx.flush().await.map(|_| r).map_err(|e| e.into()),
                     Err(e) => Err(e),
                 };
 
                 match result {
-                    Ok(_) => {
-                        // TODO: handle connection keepalive if enabled
+                    Ok(response) => {
+                        if !response.keep_alive {
+                            break false;
+                        }
+
+                        // discard the part of the body the handler didn't read
+                        match body
+                            .drain(&mut rx, http_buf, self.config.body_drain_limit)
+                            .await
+                        {
+                            Ok(f) => filled = f,
+                            Err(_) => {
+                                log!(
+                                    debug,
+                                    "Unable to discard HTTP request body, closing connection."
+                                );
+
+                                break false;
+                            }
+                        }
                     }
-                    Err(Error::Tcp(_)) => {
-                        log!(error, "TCP error while sending HTTP response.");
+                    Err(Error::Io(_)) => {
+                        log!(error, "I/O error while sending HTTP response.");
 
-                        socket.abort();
-                        _ = socket.flush().await;
-                        break;
+                        break true;
+                    }
+                    Err(e) if !started => {
+                        log!(
+                            debug,
+                            "Error while handling HTTP request, sending HTTP error page."
+                        );
+
+                        let (page, code) = self.error_page(e);
+                        let writer =
+                            ResponseWriter::<C::Transport>::new_http_11(&mut tx, Some(method));
+
+                        let _ = writer.static_page_or_empty(page, code).await;
+
+                        break false;
                     }
                     _ => {
                         log!(error, "Error while handling HTTP request.");
 
-                        // TODO: instead of sending RST, see if we can send other HTTP error codes
+                        // part of the response was already sent, so there's no way to send an error page
 
-                        socket.abort();
-                        _ = socket.flush().await;
-                        break;
+                        break true;
                     }
                 }
             }
+        };
+
+        if abort {
+            connection.abort().await;
+        } else {
+            connection.close().await;
         }
     }
 }
@@ -0,0 +1,144 @@
+//! Middlewares, that run before the router for every request.
+//!
+//! A middleware can inspect the request, answer it by itself, or add headers to the response
+//! with [`ResponseWriter::with_headers`] before passing it on:
+//!
+//! ```ignore
+//! struct SecurityHeaders;
+//!
+//! impl<S: ?Sized> Middleware<S> for SecurityHeaders {
+//!     async fn handle<'c, 'd, 'e, T: Transport>(
+//!         &self,
+//!         config: &'c HttpConfig<'d>,
+//!         state: &'c S,
+//!         reader: RequestReader<'c, 'd, 'e, T>,
+//!         writer: ResponseWriter<'c, 'd, T>,
+//!         next: &impl Next<S, T>,
+//!     ) -> Result<HttpResponse, Error> {
+//!         let headers = ExtraHeaders::new(&writer, &[("X-Content-Type-Options", "nosniff")]);
+//!
+//!         next.run(config, state, reader, writer.with_headers(&headers)).await
+//!     }
+//! }
+//!
+//! HttpServer::new(stack, &config)
+//!     .route(router! { ... })
+//!     .layer(Logger)
+//!     .layer(SecurityHeaders)
+//! ```
+//!
+//! Middlewares are added with [`crate::RoutableHttpServer::layer`], and run in the order they were added.
+
+use core::ops::AsyncFn;
+
+use crate::{
+    config::HttpConfig,
+    error::Error,
+    reader::RequestReader,
+    transport::Transport,
+    writer::{HttpResponse, ResponseWriter},
+};
+
+/// The rest of the request handling after a middleware: the next middlewares, and then the router.
+#[allow(async_fn_in_trait)]
+pub trait Next<S: ?Sized, T: Transport> {
+    async fn run<'c, 'd, 'e>(
+        &self,
+        config: &'c HttpConfig<'d>,
+        state: &'c S,
+        reader: RequestReader<'c, 'd, 'e, T>,
+        writer: ResponseWriter<'c, 'd, T>,
+    ) -> Result<HttpResponse, Error>;
+}
+
+/// Code that runs before the router for every request.
+///
+/// `S` is the application state, see [`crate::HttpServer::with_state`].
+#[allow(async_fn_in_trait)]
+pub trait Middleware<S: ?Sized = ()> {
+    /// Handles a request, usually by calling `next` to let the router handle it.
+    async fn handle<'c, 'd, 'e, T: Transport>(
+        &self,
+        config: &'c HttpConfig<'d>,
+        state: &'c S,
+        reader: RequestReader<'c, 'd, 'e, T>,
+        writer: ResponseWriter<'c, 'd, T>,
+        next: &impl Next<S, T>,
+    ) -> Result<HttpResponse, Error>;
+}
+
+/// No middleware.
+impl<S: ?Sized> Middleware<S> for () {
+    async fn handle<'c, 'd, 'e, T: Transport>(
+        &self,
+        config: &'c HttpConfig<'d>,
+        state: &'c S,
+        reader: RequestReader<'c, 'd, 'e, T>,
+        writer: ResponseWriter<'c, 'd, T>,
+        next: &impl Next<S, T>,
+    ) -> Result<HttpResponse, Error> {
+        next.run(config, state, reader, writer).await
+    }
+}
+
+/// Two middlewares, the first one runs before the second one.
+impl<S: ?Sized, A: Middleware<S>, B: Middleware<S>> Middleware<S> for (A, B) {
+    async fn handle<'c, 'd, 'e, T: Transport>(
+        &self,
+        config: &'c HttpConfig<'d>,
+        state: &'c S,
+        reader: RequestReader<'c, 'd, 'e, T>,
+        writer: ResponseWriter<'c, 'd, T>,
+        next: &impl Next<S, T>,
+    ) -> Result<HttpResponse, Error> {
+        let next = Chain {
+            middleware: &self.1,
+            next,
+        };
+
+        self.0.handle(config, state, reader, writer, &next).await
+    }
+}
+
+/// A middleware followed by the rest of the request handling.
+struct Chain<'r, M, N> {
+    middleware: &'r M,
+    next: &'r N,
+}
+
+impl<S: ?Sized, T: Transport, M: Middleware<S>, N: Next<S, T>> Next<S, T> for Chain<'_, M, N> {
+    async fn run<'c, 'd, 'e>(
+        &self,
+        config: &'c HttpConfig<'d>,
+        state: &'c S,
+        reader: RequestReader<'c, 'd, 'e, T>,
+        writer: ResponseWriter<'c, 'd, T>,
+    ) -> Result<HttpResponse, Error> {
+        self.middleware
+            .handle(config, state, reader, writer, self.next)
+            .await
+    }
+}
+
+/// The router, at the end of the middlewares.
+pub(crate) struct Router<'r, F>(pub(crate) &'r F);
+
+impl<S: ?Sized, T: Transport, F> Next<S, T> for Router<'_, F>
+where
+    F: for<'c, 'd, 'e> AsyncFn(
+        &'c HttpConfig<'d>,
+        &'c S,
+        RequestReader<'c, 'd, 'e, T>,
+        ResponseWriter<'c, 'd, T>,
+    ) -> Result<HttpResponse, Error>,
+{
+    async fn run<'c, 'd, 'e>(
+        &self,
+        config: &'c HttpConfig<'d>,
+        state: &'c S,
+        reader: RequestReader<'c, 'd, 'e, T>,
+        writer: ResponseWriter<'c, 'd, T>,
+    ) -> Result<HttpResponse, Error> {
+        self.0.async_call((config, state, reader, writer)).await
+    }
+}
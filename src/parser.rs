@@ -43,10 +43,16 @@ pub fn request<'s>(input: &mut Stream<'s>) -> ModalResult<Result<HttpRequest<'s>
 
     let _ = line_ending.parse_next(input)?;
 
+    let (path, query) = match req.path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (req.path, None),
+    };
+
     Ok(Ok(HttpRequest {
         version: req.version,
         method: req.method,
-        path: req.path,
+        path,
+        query,
         headers,
     }))
 }
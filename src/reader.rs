@@ -1,133 +1,478 @@
-use embassy_futures::select::select;
-use embassy_net::tcp::TcpReader;
-use embassy_time::Timer;
+use embassy_time::{with_deadline, with_timeout, Duration, Instant};
+use embedded_io_async::Read;
 
-use crate::{error::Error, parser, request::HttpRequest};
+use crate::{
+    error::Error,
+    headers::HeaderName,
+    parser,
+    request::HttpRequest,
+    transport::{EmbassyTcp, Transport},
+};
 
 /// Used to read HTTP requests.
 ///
 /// Uses typestate to make it impossible to misuse.
-pub struct HttpReader<'a, 'b, 'c> {
-    socket: &'a mut TcpReader<'b>,
-    pub request: HttpRequest<'c>,
+pub struct HttpReader<'a, 'b, 'c, T: Transport = EmbassyTcp> {
+    socket: &'a mut T::Reader<'b>,
+    /// The request, shared with the response writer.
+    pub request: &'c HttpRequest<'c>,
+    /// The data that was read into the HTTP buffer.
+    buf: &'c [u8],
+    /// How much of the body is left, shared with the server so it can discard it.
+    body: &'a mut BodyState,
+    /// The name of the user, once authenticated.
+    pub(crate) user: Option<&'c str>,
 }
 
-impl<'a, 'b, 'c> HttpReader<'a, 'b, 'c> {
-    pub(crate) async fn try_new(
-        socket: &'a mut TcpReader<'b>,
-        buf: &'c mut [u8],
-    ) -> Result<Self, Error> {
-        // read from the buffer until either the first newline is found, we run out of data,
-        // or if we can't find it in the buffer and there's more to read, send a bad request error.
+impl<'a, 'b, 'c, T: Transport> HttpReader<'a, 'b, 'c, T> {
+    /// Waits for the headers of a HTTP request.
+    ///
+    /// The first `filled` bytes of `buf` are data that was already received, see [`BodyState::drain`].
+    ///
+    /// If the client doesn't start sending a request within `idle_timeout`, [`Error::EOF`] is returned.
+    ///
+    /// Once the request started, the client has `header_timeout` to send all of the headers,
+    /// otherwise [`Error::Timeout`] is returned.
+    /// If the headers don't fit in `buf`, [`Error::HeadersTooLarge`] is returned.
+    ///
+    /// Returns where the headers end in `buf`, and how much data was read into it, to parse them with [`HttpReader::parse`].
+    pub(crate) async fn receive(
+        socket: &mut T::Reader<'b>,
+        buf: &mut [u8],
+        filled: usize,
+        idle_timeout: Duration,
+        header_timeout: Duration,
+    ) -> Result<(usize, usize), Error> {
+        let mut total = filled;
 
-        let mut total = 0usize;
-        //let mut body_inline = false;
+        if total == 0 {
+            // wait for the client to start a new request, the connection is idle until then
+            total = match with_timeout(idle_timeout, socket.read(buf)).await {
+                Ok(c) => c?,
+                Err(_) => return Err(Error::EOF),
+            };
+
+            if total == 0 {
+                return Err(Error::EOF);
+            }
+        }
+
+        let deadline = Instant::now() + header_timeout;
+        let mut searched = 0usize;
+
+        // keep reading until the empty line that ends the headers
+        let header_end = loop {
+            if let Some(end) = find_header_end(&buf[..total], searched) {
+                break end;
+            }
+            // the terminator could be split between this read and the next one
+            searched = total.saturating_sub(3);
 
-        loop {
             if buf.len() == total {
                 // our buffer is full
-                break;
+                return Err(Error::HeadersTooLarge);
             }
-            let rbuf = &mut buf[total..];
 
-            // bad idea
-            let count = match select(socket.read(rbuf), Timer::after_millis(3)).await {
-                embassy_futures::select::Either::First(c) => c?,
-                embassy_futures::select::Either::Second(_) => break,
+            let count = match with_deadline(deadline, socket.read(&mut buf[total..])).await {
+                Ok(c) => c?,
+                Err(_) => return Err(Error::Timeout),
             };
             if count == 0 {
-                // we ran out of data
-                // if the request has a body, it should be within our buffer
-                //body_inline = true;
-                break;
+                // the client stopped sending data in the middle of the request
+                return Err(Error::BadRequest);
             }
             total += count;
-        }
+        };
 
-        if total == 0 {
-            return Err(Error::EOF);
-        }
+        Ok((header_end, total))
+    }
 
-        // replace with actual minimum
-        if total < 15 {
-            return Err(Error::BadRequest);
-        }
-        let buf = &buf[0..total];
+    /// Parses a request received with [`HttpReader::receive`], whose headers end at `header_end` in `buf`.
+    ///
+    /// `body` is set up to read the body that follows the headers.
+    pub(crate) fn parse(
+        buf: &'c [u8],
+        header_end: usize,
+        body: &mut BodyState,
+    ) -> Result<HttpRequest<'c>, Error> {
+        let request = parser::parse_request(&buf[..header_end])?;
+
+        // a chunked body ignores the Content-Length header
+        let (framing, len) = match request.try_find_header(&HeaderName::TransferEncoding) {
+            Some(coding) if coding.trim().eq_ignore_ascii_case("chunked") => {
+                (Framing::Chunked(Chunk::SizeStart), 0)
+            }
+            // we can't find the end of a body with any other transfer coding
+            Some(_) => return Err(Error::BadRequest),
+            None => match request.try_find_header(&HeaderName::ContentLength) {
+                Some(len) => (
+                    Framing::Length,
+                    str::parse(len.trim()).map_err(|_| Error::BadRequest)?,
+                ),
+                None => (Framing::Length, 0),
+            },
+        };
 
-        let request = parser::parse_request(buf)?;
+        *body = BodyState {
+            framing,
+            remaining: len,
+            consumed: 0,
+            start: header_end,
+            end: buf.len(),
+        };
 
-        // TODO: check if the request body is fully contained in the RX buffer.
-        // if it isn't, then let the body reader handle it.
+        Ok(request)
+    }
 
-        Ok(Self { socket, request })
+    /// Creates a reader for `request`, whose headers and the start of the body were received into `buf`.
+    pub(crate) fn new(
+        socket: &'a mut T::Reader<'b>,
+        request: &'c HttpRequest<'c>,
+        buf: &'c [u8],
+        body: &'a mut BodyState,
+    ) -> Self {
+        Self {
+            socket,
+            request,
+            buf,
+            body,
+            user: None,
+        }
+    }
+
+    /// Gets the name of the user that sent the request, if the route is protected with authentication.
+    pub fn user(&self) -> Option<&'c str> {
+        self.user
     }
 
     /// Returns a handle to read the full body streaming.
-    /// Returns [`None`] if there's no body or if the body is inline.
-    /// For more information, see [`HttpRequest::body_inline`]
-    pub fn body(self) -> Option<HttpBodyReader<'a, 'b>> {
-        let str = self.request.try_find_header(&crate::headers::HeaderName::ContentLength)?;
-        let len = str::parse(str).ok()?;
-        Some(HttpBodyReader::new(self.socket, len))
+    /// Returns [`None`] if there's no body.
+    ///
+    /// The body bytes that were already received together with the headers are returned first.
+    /// Chunked bodies are decoded transparently.
+    pub fn body(self) -> Option<HttpBodyReader<'a, 'b, 'c, T>> {
+        if self.body.framing == Framing::Length && self.body.remaining == 0 {
+            return None;
+        }
+
+        Some(HttpBodyReader {
+            socket: self.socket,
+            buf: self.buf,
+            body: self.body,
+        })
     }
 }
 
-pub type RequestReader<'a, 'b, 'c> = HttpReader<'a, 'b, 'c>;
+pub type RequestReader<'a, 'b, 'c, T = EmbassyTcp> = HttpReader<'a, 'b, 'c, T>;
 
 /// Used to read HTTP response bodies.
 ///
 /// Uses typestate to make it impossible to misuse.
-pub struct HttpBodyReader<'a, 'b> {
-    socket: &'a mut TcpReader<'b>,
+pub struct HttpBodyReader<'a, 'b, 'c, T: Transport = EmbassyTcp> {
+    socket: &'a mut T::Reader<'b>,
+    /// The data that was read into the HTTP buffer together with the request headers.
+    buf: &'c [u8],
+    body: &'a mut BodyState,
+}
+
+impl<'a, 'b, 'c, T: Transport> HttpBodyReader<'a, 'b, 'c, T> {
+    pub async fn try_read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.body.read(self.socket, self.buf, buf).await
+    }
+
     /// The length of the HTTP body, in bytes.
-    len: usize,
+    ///
+    /// Returns [`None`] if the body is chunked, as its length isn't known until it's fully read.
+    pub fn len(&self) -> Option<usize> {
+        match self.body.framing {
+            Framing::Length => Some(self.body.consumed + self.body.remaining),
+            Framing::Chunked(_) => None,
+        }
+    }
+
     /// The amount of data read from the HTTP body, in bytes.
-    read: usize,
+    pub fn read(&self) -> usize {
+        self.body.consumed
+    }
 }
 
-impl<'a, 'b> HttpBodyReader<'a, 'b> {
-    fn new(socket: &'a mut TcpReader<'b>, len: usize) -> Self {
-        // TODO: include the inline data somehow
-        Self {
-            socket,
-            len,
-            read: 0,
+/// How the end of the request body is found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Framing {
+    /// The body has a known length, from the `Content-Length` header.
+    #[default]
+    Length,
+    /// The body uses chunked transfer encoding.
+    Chunked(Chunk),
+}
+
+/// Where the chunked body decoder is at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Chunk {
+    /// Start of the chunk size.
+    SizeStart,
+    /// Inside the hexadecimal chunk size.
+    Size,
+    /// After the chunk size, skipping extensions until the end of the line.
+    SizeLine,
+    /// Inside the chunk data.
+    Data,
+    /// The newline after the chunk data.
+    DataEnd,
+    /// Start of a trailer line, or the empty line that ends the body.
+    Trailers,
+    /// Inside a trailer line, which is skipped.
+    TrailerLine,
+    /// The whole body was read.
+    Done,
+}
+
+impl Chunk {
+    /// Advances the decoder by one byte of chunk framing, updating the chunk `size`.
+    fn next(self, byte: u8, size: &mut usize) -> Result<Chunk, Error> {
+        let digit = (byte as char).to_digit(16).map(|d| d as usize);
+
+        let next = match (self, byte) {
+            (Chunk::SizeStart, _) => {
+                *size = digit.ok_or(Error::BadRequest)?;
+                Chunk::Size
+            }
+            (Chunk::Size, b'\n') | (Chunk::SizeLine, b'\n') if *size == 0 => Chunk::Trailers,
+            (Chunk::Size, b'\n') | (Chunk::SizeLine, b'\n') => Chunk::Data,
+            (Chunk::Size, _) => match digit {
+                Some(digit) => {
+                    *size = size
+                        .checked_mul(16)
+                        .and_then(|s| s.checked_add(digit))
+                        .ok_or(Error::EntityTooLarge)?;
+                    Chunk::Size
+                }
+                None => Chunk::SizeLine,
+            },
+            (Chunk::SizeLine, _) => Chunk::SizeLine,
+            (Chunk::DataEnd, b'\r') => Chunk::DataEnd,
+            (Chunk::DataEnd, b'\n') => Chunk::SizeStart,
+            (Chunk::DataEnd, _) => return Err(Error::BadRequest),
+            (Chunk::Trailers, b'\r') => Chunk::Trailers,
+            (Chunk::Trailers, b'\n') => Chunk::Done,
+            (Chunk::Trailers, _) => Chunk::TrailerLine,
+            (Chunk::TrailerLine, b'\n') => Chunk::Trailers,
+            (Chunk::TrailerLine, _) => Chunk::TrailerLine,
+            (Chunk::Data, _) | (Chunk::Done, _) => self,
+        };
+
+        Ok(next)
+    }
+}
+
+/// Keeps track of the request body, so that whatever the handler didn't read
+/// can be discarded before reading the next request on the same connection.
+#[derive(Debug, Default)]
+pub(crate) struct BodyState {
+    framing: Framing,
+    /// The amount of data left in the body (or in the current chunk, if chunked), in bytes.
+    remaining: usize,
+    /// The amount of data read from the body, in bytes.
+    consumed: usize,
+    /// Start of the data in the HTTP buffer that wasn't consumed yet.
+    start: usize,
+    /// End of the data in the HTTP buffer.
+    end: usize,
+}
+
+impl BodyState {
+    /// Reads part of the body, first from the HTTP buffer and then from the socket.
+    ///
+    /// Never reads past the end of the body, so the next request is left untouched.
+    async fn read(
+        &mut self,
+        socket: &mut impl Read,
+        http_buf: &[u8],
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        let mut chunk = match self.framing {
+            Framing::Length => return self.read_data(socket, http_buf, buf).await,
+            Framing::Chunked(chunk) => chunk,
+        };
+
+        loop {
+            match chunk {
+                Chunk::Done => return Ok(0),
+                Chunk::Data => {
+                    let read = self.read_data(socket, http_buf, buf).await?;
+                    if self.remaining == 0 {
+                        self.framing = Framing::Chunked(Chunk::DataEnd);
+                    }
+                    return Ok(read);
+                }
+                _ => {
+                    // go through the framing one byte at a time, to never read past it
+                    let byte = self.read_byte(socket, http_buf).await?;
+                    chunk = chunk.next(byte, &mut self.remaining)?;
+                    self.framing = Framing::Chunked(chunk);
+                }
+            }
         }
     }
 
-    pub async fn try_read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        if self.read == self.len {
+    /// Reads body data, up to the end of the body (or current chunk).
+    async fn read_data(
+        &mut self,
+        socket: &mut impl Read,
+        http_buf: &[u8],
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        let count = buf.len().min(self.remaining);
+        if count == 0 {
             return Ok(0);
         }
-        let read = self.socket.read(buf).await?;
-        if read == 0 {
-            return Err(Error::EOF);
-        }
-        self.read += read;
+
+        let read = if self.start < self.end {
+            let count = count.min(self.end - self.start);
+            buf[..count].copy_from_slice(&http_buf[self.start..self.start + count]);
+            self.start += count;
+            count
+        } else {
+            let read = socket.read(&mut buf[..count]).await?;
+            if read == 0 {
+                return Err(Error::EOF);
+            }
+            read
+        };
+        self.remaining -= read;
+        self.consumed += read;
 
         Ok(read)
     }
 
-    pub fn len(&self) -> usize {
-        self.len
+    /// Reads a single byte of chunk framing.
+    async fn read_byte(&mut self, socket: &mut impl Read, http_buf: &[u8]) -> Result<u8, Error> {
+        if self.start < self.end {
+            self.start += 1;
+            return Ok(http_buf[self.start - 1]);
+        }
+
+        let mut byte = [0u8];
+        if socket.read(&mut byte).await? == 0 {
+            return Err(Error::EOF);
+        }
+
+        Ok(byte[0])
     }
 
-    pub fn read(&self) -> usize {
-        self.read
+    /// Discards the rest of the body, reading at most `limit` bytes of it.
+    ///
+    /// Any data received after the body is moved to the start of `http_buf`, and its length is returned,
+    /// so it can be passed to [`HttpReader::receive`].
+    ///
+    /// If the body is larger than `limit`, [`Error::EntityTooLarge`] is returned and the connection should be closed.
+    pub(crate) async fn drain(
+        &mut self,
+        socket: &mut impl Read,
+        http_buf: &mut [u8],
+        limit: usize,
+    ) -> Result<usize, Error> {
+        if self.framing == Framing::Length && self.remaining > limit {
+            return Err(Error::EntityTooLarge);
+        }
+
+        let mut discard = [0u8; 64];
+        let mut discarded = 0usize;
+
+        loop {
+            let read = self.read(socket, http_buf, &mut discard).await?;
+            if read == 0 {
+                break;
+            }
+
+            discarded += read;
+            if discarded > limit {
+                return Err(Error::EntityTooLarge);
+            }
+        }
+
+        // keep any pipelined request for the next read
+        http_buf.copy_within(self.start..self.end, 0);
+
+        Ok(self.end - self.start)
     }
 }
 
-/*
-impl<'a, 'b, 'c> Drop for HttpReader<'a, 'b, 'c> {
-    fn drop(&mut self) {
-        // TODO: if a HTTP body does exist, read (and discard) it from the TCP buffer
-    }
+/// Finds the empty line that ends the request headers, starting the search at `from`.
+///
+/// Returns the index of the first byte after it.
+fn find_header_end(buf: &[u8], from: usize) -> Option<usize> {
+    buf[from..]
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|pos| from + pos + 4)
 }
 
-impl<'a, 'b> Drop for HttpBodyReader<'a, 'b> {
-    fn drop(&mut self) {
-        // TODO: if the HTTP body hasn't been read to completion, read (and discard)
-        // the rest of it from the TCP buffer.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds chunk framing to the decoder, starting at a chunk size.
+    fn decode(framing: &[u8]) -> Result<(Chunk, usize), Error> {
+        let mut state = Chunk::SizeStart;
+        let mut size = 0;
+        for byte in framing {
+            state = state.next(*byte, &mut size)?;
+        }
+
+        Ok((state, size))
+    }
+
+    #[test]
+    fn chunk_size() {
+        assert_eq!(decode(b"1a\r\n"), Ok((Chunk::Data, 26)));
+        assert_eq!(decode(b"FF\r\n"), Ok((Chunk::Data, 255)));
+        assert_eq!(decode(b"5"), Ok((Chunk::Size, 5)));
+        assert_eq!(decode(b"x\r\n"), Err(Error::BadRequest));
+        assert_eq!(decode(b"\r\n"), Err(Error::BadRequest));
+    }
+
+    #[test]
+    fn chunk_extensions() {
+        assert_eq!(decode(b"10;name=value\r\n"), Ok((Chunk::Data, 16)));
+        assert_eq!(decode(b"10 ; name=\"a;b\"\r\n"), Ok((Chunk::Data, 16)));
+        // digits after an extension aren't part of the size
+        assert_eq!(decode(b"2;3\r\n"), Ok((Chunk::Data, 2)));
+    }
+
+    #[test]
+    fn chunk_bare_newlines() {
+        assert_eq!(decode(b"4\n"), Ok((Chunk::Data, 4)));
+        assert_eq!(decode(b"0\n\n"), Ok((Chunk::Done, 0)));
+
+        let mut size = 0;
+        assert_eq!(Chunk::DataEnd.next(b'\n', &mut size), Ok(Chunk::SizeStart));
+        assert_eq!(Chunk::DataEnd.next(b'\r', &mut size), Ok(Chunk::DataEnd));
+        assert_eq!(Chunk::DataEnd.next(b'x', &mut size), Err(Error::BadRequest));
+    }
+
+    #[test]
+    fn chunk_size_overflow() {
+        let mut framing = [b'f'; 2 * core::mem::size_of::<usize>() + 1];
+        assert_eq!(decode(&framing), Err(Error::EntityTooLarge));
+
+        framing[0] = b'0';
+        assert_eq!(decode(&framing), Ok((Chunk::Size, usize::MAX)));
+    }
+
+    #[test]
+    fn chunk_last() {
+        assert_eq!(decode(b"0\r\n"), Ok((Chunk::Trailers, 0)));
+        assert_eq!(decode(b"0\r\n\r\n"), Ok((Chunk::Done, 0)));
+        assert_eq!(decode(b"000;ext\r\n\r\n"), Ok((Chunk::Done, 0)));
+        assert_eq!(decode(b"0\r\nExpires: never\r\n"), Ok((Chunk::Trailers, 0)));
+        assert_eq!(
+            decode(b"0\r\nExpires: never\r\nX-Sum: 1\r\n\r\n"),
+            Ok((Chunk::Done, 0))
+        );
+
+        // anything after the end of the body belongs to the next request
+        let mut size = 0;
+        assert_eq!(Chunk::Done.next(b'G', &mut size), Ok(Chunk::Done));
     }
 }
-*/
@@ -1,4 +1,6 @@
+use crate::error::Error;
 use crate::headers::HeaderName;
+use crate::url::{self, Encoded, QueryParams};
 use cfg_if::cfg_if;
 
 /// Specifies the version of HTTP supported by the client.
@@ -37,6 +39,44 @@ pub enum HttpMethod {
     Connect,
 }
 
+impl HttpMethod {
+    /// Every HTTP method.
+    pub const ALL: [HttpMethod; 9] = [
+        HttpMethod::Get,
+        HttpMethod::Head,
+        HttpMethod::Post,
+        HttpMethod::Options,
+        HttpMethod::Trace,
+        HttpMethod::Delete,
+        HttpMethod::Put,
+        HttpMethod::Patch,
+        HttpMethod::Connect,
+    ];
+
+    /// Gets the name of the method, as sent in the request line.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Head => "HEAD",
+            HttpMethod::Post => "POST",
+            HttpMethod::Options => "OPTIONS",
+            HttpMethod::Trace => "TRACE",
+            HttpMethod::Delete => "DELETE",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Patch => "PATCH",
+            HttpMethod::Connect => "CONNECT",
+        }
+    }
+
+    /// Gets the method of a request from the start of its request line, even if the rest of it is invalid.
+    pub fn from_request_line(line: &[u8]) -> Option<HttpMethod> {
+        HttpMethod::ALL.into_iter().find(|method| {
+            line.strip_prefix(method.as_str().as_bytes())
+                .is_some_and(|rest| rest.starts_with(b" "))
+        })
+    }
+}
+
 #[cfg(not(any(
     feature = "max_headers_16",
     feature = "max_headers_24",
@@ -86,6 +126,11 @@ pub struct HttpRequest<'a> {
     /// See [`HttpRequest::path()`].
     pub(crate) path: &'a str,
 
+    /// The query string used by the client, without the `?`.
+    ///
+    /// See [`HttpRequest::query()`].
+    pub(crate) query: Option<&'a str>,
+
     /// The HTTP request headers.
     ///
     /// See [`HttpRequest::try_find_header()`].
@@ -98,13 +143,39 @@ impl<'a> HttpRequest<'a> {
         self.version
     }
 
-    /// Gets the resource path asked by the client.
-    pub fn path(&'a self) -> &'a str {
+    /// Gets the resource path asked by the client, without the query string.
+    ///
+    /// The path is still percent-encoded, see [`HttpRequest::decoded_path()`].
+    pub fn path(&self) -> &'a str {
         self.path
     }
 
+    /// Decodes the resource path asked by the client into `buf`.
+    ///
+    /// See [`url::percent_decode()`].
+    pub fn decoded_path<'b>(&self, buf: &'b mut [u8]) -> Result<&'b str, Error> {
+        url::percent_decode(self.path, buf)
+    }
+
+    /// Gets the raw query string sent by the client, without the `?`, if there is one.
+    pub fn query(&self) -> Option<&'a str> {
+        self.query
+    }
+
+    /// Iterates over the key/value pairs of the query string.
+    pub fn query_params(&self) -> QueryParams<'a> {
+        QueryParams::new(self.query.unwrap_or(""))
+    }
+
+    /// Gets the value of the first query parameter with the specified (decoded) name, if one exists.
+    pub fn query_param(&self, name: &str) -> Option<Encoded<'a>> {
+        self.query_params()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
     /// Gets the HTTP method used by the client.
-    pub fn method(&'a self) -> HttpMethod {
+    pub fn method(&self) -> HttpMethod {
         self.method
     }
 
@@ -113,7 +184,27 @@ impl<'a> HttpRequest<'a> {
     /// When the `max_headers_16`/`max_headers_24` features are enabled, the search is `O(N)`
     ///
     /// When the `max_headers_32`/`max_headers_48`/`max_headers_64` features are enabled, the search is `O(1)`
-    pub fn try_find_header(&'a self, header: &HeaderName<'_>) -> Option<&'a str> {
-        self.headers.get(header).map(|v| &**v)
+    pub fn try_find_header(&self, header: &HeaderName<'_>) -> Option<&'a str> {
+        self.headers.get(header).copied()
+    }
+
+    /// Checks whether the client wants to keep the connection open after this request.
+    ///
+    /// HTTP/1.1 connections are persistent unless the client sends `Connection: close`,
+    /// HTTP/1.0 connections are closed unless the client sends `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        let mut keep_alive = self.version == HttpVersion::Http11;
+
+        if let Some(connection) = self.try_find_header(&HeaderName::Connection) {
+            for option in connection.split(',').map(str::trim) {
+                if option.eq_ignore_ascii_case("close") {
+                    return false;
+                } else if option.eq_ignore_ascii_case("keep-alive") {
+                    keep_alive = true;
+                }
+            }
+        }
+
+        keep_alive
     }
 }
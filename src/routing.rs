@@ -2,86 +2,507 @@
 #[doc(hidden)]
 pub use base64;
 
+use core::str::FromStr;
+
+use crate::{
+    config::HttpConfig,
+    error::Error,
+    headers::HeaderName,
+    request::{HttpMethod, HttpRequest},
+    status::StatusCode,
+    transport::Transport,
+    url::Encoded,
+    utils,
+    writer::{HttpResponse, ResponseWriter},
+};
+
+/// A set of HTTP methods, used to answer with the methods a route supports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MethodSet(u16);
+
+impl MethodSet {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Every HTTP method.
+    pub const fn all() -> Self {
+        Self((1 << HttpMethod::ALL.len()) - 1)
+    }
+
+    pub fn insert(&mut self, method: HttpMethod) {
+        self.0 |= 1 << method as u16;
+    }
+
+    pub fn contains(&self, method: HttpMethod) -> bool {
+        self.0 & (1 << method as u16) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Writes the methods as a comma separated list, like in the `Allow` header.
+    pub fn as_str<'b>(&self, buf: &'b mut [u8; 64]) -> &'b str {
+        let mut len = 0;
+
+        for method in HttpMethod::ALL.iter().filter(|m| self.contains(**m)) {
+            if len != 0 {
+                buf[len..len + 2].copy_from_slice(b", ");
+                len += 2;
+            }
+            let name = method.as_str().as_bytes();
+            buf[len..len + name.len()].copy_from_slice(name);
+            len += name.len();
+        }
+
+        // This never panics, every method name is ASCII
+        core::str::from_utf8(&buf[..len]).unwrap()
+    }
+}
+
+/// Checks whether a request is a CORS preflight request, that must be answered by the server instead of a handler.
+///
+/// This is always false if [`HttpConfig::cors`] isn't set.
+pub fn is_preflight(config: &HttpConfig, request: &HttpRequest) -> bool {
+    config.cors.is_some()
+        && request.method() == HttpMethod::Options
+        && request
+            .try_find_header(&HeaderName::AccessControlRequestMethod)
+            .is_some()
+}
+
+/// Answers an `OPTIONS` request for a path that accepts the `allowed` methods.
+///
+/// If it's a CORS preflight request from an allowed origin, the methods and headers it can use are sent too.
+pub async fn send_options<T: Transport>(
+    config: &HttpConfig<'_>,
+    request: &HttpRequest<'_>,
+    writer: ResponseWriter<'_, '_, T>,
+    mut allowed: MethodSet,
+) -> Result<HttpResponse, Error> {
+    allowed.insert(HttpMethod::Options);
+
+    let mut buf = [0u8; 64];
+    let methods = allowed.as_str(&mut buf);
+
+    let mut writer = writer
+        .start(StatusCode::NO_CONTENT)
+        .await?
+        .header("Allow", methods)
+        .await?;
+
+    let cors = config
+        .cors
+        .filter(|cors| is_preflight(config, request) && cors.allow_origin(request).is_some());
+
+    if let Some(cors) = cors {
+        writer = writer
+            .header("Access-Control-Allow-Methods", methods)
+            .await?;
+
+        let headers = cors
+            .allowed_headers
+            .or_else(|| request.try_find_header(&HeaderName::AccessControlRequestHeaders));
+        if let Some(headers) = headers {
+            writer = writer
+                .header("Access-Control-Allow-Headers", headers)
+                .await?;
+        }
+
+        if let Some(max_age) = cors.max_age {
+            let mut buf = utils::USizeStrBuf::new();
+            writer = writer
+                .header("Access-Control-Max-Age", buf.stringify(max_age as usize))
+                .await?;
+        }
+    }
+
+    writer.body_empty().await
+}
+
+/// Checks whether a request path matches a route pattern.
+///
+/// A pattern segment can be:
+/// - a literal, that must be equal to the path segment
+/// - `{name}`, that matches any non-empty path segment, and passes it to `capture`
+/// - `{*name}`, only at the end, that matches the rest of the path (even if empty), and passes it to `capture`
+/// - `*`, only at the end, that matches the rest of the path
+pub fn match_route<'p>(
+    pattern: &str,
+    path: &'p str,
+    mut capture: impl FnMut(&str, &'p str),
+) -> bool {
+    let mut pattern = pattern;
+    let mut path = path;
+
+    loop {
+        let (segment, pattern_rest) = split_segment(pattern);
+
+        if segment == "*" {
+            return true;
+        }
+        if let Some(name) = segment.strip_prefix("{*").and_then(|s| s.strip_suffix('}')) {
+            capture(name, path);
+            return true;
+        }
+
+        let (value, path_rest) = split_segment(path);
+
+        if let Some(name) = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+            if value.is_empty() {
+                return false;
+            }
+            capture(name, value);
+        } else if segment != value {
+            return false;
+        }
+
+        match (pattern_rest, path_rest) {
+            (None, None) => return true,
+            (Some(pattern_rest), Some(path_rest)) => {
+                pattern = pattern_rest;
+                path = path_rest;
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// Checks whether a route pattern has a `{name}` or `{*name}` segment, see [`match_route`].
+///
+/// Used by [`crate::router!`] to reject handler parameters that aren't part of their route at compile time.
+pub const fn route_has_param(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let name = name.as_bytes();
+
+    let mut i = 0;
+    while i < pattern.len() {
+        // parameters are whole segments
+        if pattern[i] == b'{' && (i == 0 || pattern[i - 1] == b'/') {
+            let wildcard = i + 1 < pattern.len() && pattern[i + 1] == b'*';
+            let start = if wildcard { i + 2 } else { i + 1 };
+            let end = start + name.len();
+
+            let closed = end < pattern.len() && pattern[end] == b'}';
+            // wildcards are only allowed at the end
+            let segment_end = closed
+                && if wildcard {
+                    end + 1 == pattern.len()
+                } else {
+                    end + 1 == pattern.len() || pattern[end + 1] == b'/'
+                };
+
+            if segment_end {
+                let mut j = 0;
+                while j < name.len() && pattern[start + j] == name[j] {
+                    j += 1;
+                }
+                if j == name.len() {
+                    return true;
+                }
+            }
+        }
+        i += 1;
+    }
+
+    false
+}
+
+/// Maximum length of the decoded value of a path parameter with a type.
+pub const MAX_PARAM_LEN: usize = 128;
+
+/// Decodes a path parameter and parses it with [`FromStr`], for the parameters of [`crate::router!`] that have a type.
+///
+/// Returns [`None`] if the decoded value is longer than [`MAX_PARAM_LEN`] or can't be parsed.
+pub fn parse_param<P: FromStr>(value: &str) -> Option<P> {
+    let value = Encoded::path(value);
+
+    let mut buf = [0u8; MAX_PARAM_LEN];
+    let decoded = match value.as_str() {
+        Some(value) => value,
+        None => value.decode_into(&mut buf).ok()?,
+    };
+
+    decoded.parse().ok()
+}
+
+/// Splits the first path segment from the rest of the path.
+fn split_segment(path: &str) -> (&str, Option<&str>) {
+    match path.split_once('/') {
+        Some((segment, rest)) => (segment, Some(rest)),
+        None => (path, None),
+    }
+}
+
+/// Creates a router function to pass to [`crate::HttpServer::route`].
+///
+/// Each route is a path, optionally preceded by the method it accepts:
+///
+/// ```ignore
+/// router! {
+///     "/" => index,
+///     GET "/led" => get_led,
+///     POST "/led" => set_led,
+///     GET "/api/sensor/{id}/value" => sensor_value(id: u8),
+///     GET "/files/{*path}" => file(path),
+/// }
+/// ```
+///
+/// Routes without a method accept every method. `GET` routes also accept `HEAD` requests.
+///
+/// Paths can contain parameters, see [`match_route`] for the syntax.
+/// The parameters listed after the handler are passed to it after the reader and the writer,
+/// either as a `&str` borrowed from the request, or decoded and parsed with [`core::str::FromStr`] if a type is specified.
+/// The `&str` parameters are still percent-encoded, like they were sent: decode them with [`crate::url::Encoded::path`].
+/// If a parameter fails to parse, a 400 Bad Request is sent. Parameters that aren't part of their route don't compile.
+///
+/// The state set with [`crate::HttpServer::with_state`] is passed to every handler right after the writer,
+/// if its type is declared before the routes:
+///
+/// ```ignore
+/// router! {
+///     state: Sensors;
+///     GET "/api/temperature" => temperature,
+/// }
+///
+/// async fn temperature<T: Transport>(
+///     reader: RequestReader<'_, '_, '_, T>,
+///     writer: ResponseWriter<'_, '_, T>,
+///     sensors: &Sensors,
+/// ) -> Result<HttpResponse, Error> { ... }
+/// ```
+///
+/// Routes can be protected with HTTP basic authentication, by adding a [`crate::auth::BasicAuth`] after the handler:
+///
+/// ```ignore
+/// const ADMIN_CREDS: BasicAuth = BasicAuth {
+///     realm: "admin",
+///     credentials: &[("admin", "hunter2")],
+/// };
+///
+/// router! {
+///     "/admin/*" => admin [auth = ADMIN_CREDS],
+///     POST "/api/led/{id}" => set_led(id: u8) [auth = ADMIN_CREDS],
+/// }
+/// ```
+///
+/// Requests without valid credentials get a 401 Unauthorized, using [`crate::config::HttpConfig::http_401`].
+/// The handler can get the name of the user with [`crate::reader::HttpReader::user`].
+///
+/// The credentials can come from any [`crate::auth::CredentialStore`].
+/// Any other [`crate::auth::Authenticator`] can be used the same way, like [`crate::digest_auth::DigestAuth`]
+/// with the `digest_auth` feature.
+///
+/// A [`crate::files::StaticDir`] can be added after the routes, to serve its files for paths that don't match any route:
+///
+/// ```ignore
+/// router! {
+///     GET "/api/led" => get_led,
+///     _ => WEB_UI,
+/// }
+/// ```
+///
+/// `OPTIONS` requests for paths without an `OPTIONS` route are answered automatically with the methods they accept.
+/// If [`crate::config::HttpConfig::cors`] is set, CORS preflight requests are always answered automatically.
+///
+/// Paths that exist but don't accept the request method get a 405 Method Not Allowed,
+/// and every other path gets a 404 Not Found.
 #[macro_export]
 macro_rules! router {
-    (
+    (@method GET) => { $crate::request::HttpMethod::Get };
+    (@method HEAD) => { $crate::request::HttpMethod::Head };
+    (@method POST) => { $crate::request::HttpMethod::Post };
+    (@method OPTIONS) => { $crate::request::HttpMethod::Options };
+    (@method TRACE) => { $crate::request::HttpMethod::Trace };
+    (@method DELETE) => { $crate::request::HttpMethod::Delete };
+    (@method PUT) => { $crate::request::HttpMethod::Put };
+    (@method PATCH) => { $crate::request::HttpMethod::Patch };
+    (@method CONNECT) => { $crate::request::HttpMethod::Connect };
+
+    // routes without a method accept anything
+    (@matches $request_method:ident) => { true };
+    (@matches $request_method:ident GET) => {
+        $request_method == $crate::request::HttpMethod::Get
+            || $request_method == $crate::request::HttpMethod::Head
+    };
+    (@matches $request_method:ident $method:ident) => {
+        $request_method == $crate::router!(@method $method)
+    };
+
+    (@allow $allowed:ident) => {
+        $allowed = $crate::routing::MethodSet::all();
+    };
+    (@allow $allowed:ident GET) => {
+        $allowed.insert($crate::request::HttpMethod::Get);
+        $allowed.insert($crate::request::HttpMethod::Head);
+    };
+    (@allow $allowed:ident $method:ident) => {
+        $allowed.insert($crate::router!(@method $method));
+    };
+
+    // path parameters without a type are passed as they are
+    (@param $config:ident, $writer:ident, $value:ident) => { $value };
+    (@param $config:ident, $writer:ident, $value:ident $ty:ty) => {
+        match $crate::routing::parse_param::<$ty>($value) {
+            Some(value) => value,
+            None => {
+                $crate::log!(debug, "Invalid path parameter '{}', sending HTTP 400", $value);
+
+                return $writer
+                    .static_page_or_empty(
+                        $config.http_400,
+                        $crate::status::StatusCode::BAD_REQUEST
+                    ).await;
+            }
+        }
+    };
+
+    // the state is only passed to the handlers if its type was declared
+    (@call $func:ident [] ($($args:ident),*) $state:ident ($($param:ident),*)) => {
+        $func($($args),* $(, $param)*)
+    };
+    (@call $func:ident [with_state] ($($args:ident),*) $state:ident ($($param:ident),*)) => {
+        $func($($args),*, $state $(, $param)*)
+    };
+
+    (@routes $state_type:ty, $with_state:tt,
         $(
-            $route:literal => $func:ident,
-        )+
+            $($method:ident)? $route:literal => $func:ident $(($($param:ident $(: $ty:ty)?),* $(,)?))? $([auth = $auth:expr])?,
+        )*
+        $(_ => $files:expr $(,)?)?
     ) => {
         {
-        async fn routerfn<'a, 'b, 'c>(config: &'a $crate::config::HttpConfig<'b>, reader: $crate::reader::RequestReader<'a, 'b, 'c>,
-         writer: $crate::writer::ResponseWriter<'a, 'b>) -> Result<$crate::writer::HttpResponse, $crate::error::Error> {
-            match reader.request.path() {
-                $(
-                    $route => {
-                        $crate::log!(debug, "Routing page '{}' to {}", reader.request.path(), stringify!($func));
-
-                        $func(reader, writer).await
-                    },
-                    )+
-                _ => {
-                        $crate::log!(debug, "Routing page '{}' to 404", reader.request.path());
-
-                        // handle 404s
-                        writer
-                        .static_page_or_empty(
-                            config.http_404,
-                            $crate::status::StatusCode::NOT_FOUND
-                        ).await
+        async fn routerfn<'a, 'b, 'c, T: $crate::transport::Transport>(config: &'a $crate::config::HttpConfig<'b>,
+         state: &'a $state_type, reader: $crate::reader::RequestReader<'a, 'b, 'c, T>,
+         writer: $crate::writer::ResponseWriter<'a, 'b, T>) -> Result<$crate::writer::HttpResponse, $crate::error::Error> {
+            let path = reader.request.path();
+            let request_method = reader.request.method();
+            // preflight requests are never passed to the handlers
+            let preflight = $crate::routing::is_preflight(config, reader.request);
+            // methods accepted by the requested path
+            let mut allowed = $crate::routing::MethodSet::new();
+
+            $(
+                {
+                    $($(
+                        const _: () = assert!(
+                            $crate::routing::route_has_param($route, stringify!($param)),
+                            concat!("parameter `", stringify!($param), "` is not part of its route"),
+                        );
+                        let mut $param: Option<&str> = None;
+                    )*)?
+
+                    let matches = $crate::routing::match_route($route, path, |_name, _value| {
+                        $($(
+                            if _name == stringify!($param) {
+                                $param = Some(_value);
+                            }
+                        )*)?
+                    });
+
+                    if matches {
+                        if !preflight && $crate::router!(@matches request_method $($method)?) {
+                            #[allow(unused_mut)]
+                            let mut reader = reader;
+                            $(
+                                let auth = &$auth;
+                                if let Err(rejection) = $crate::auth::authenticate(auth, &mut reader).await {
+                                    $crate::log!(debug, "Asking for authentication to access page {}", path);
+
+                                    return $crate::auth::Authenticator::challenge(auth, config, rejection, writer).await;
+                                }
+                            )?
+
+                            $crate::log!(debug, "Routing page '{}' to {}", path, stringify!($func));
+
+                            $($(
+                                // always captured, the route was checked to have it at compile time
+                                let $param = $param.unwrap_or_default();
+                                let $param = $crate::router!(@param config, writer, $param $($ty)?);
+                            )*)?
+
+                            return $crate::router!(@call $func $with_state (reader, writer) state ($($($param),*)?)).await;
+                        }
+
+                        $crate::router!(@allow allowed $($method)?);
                     }
                 }
+            )*
+
+            // static files are only served if no route matches
+            $(
+                if let Some(file) = $crate::files::StaticDir::find_request(&$files, reader.request) {
+                    if !preflight && $crate::router!(@matches request_method GET) {
+                        $crate::log!(debug, "Serving static file '{}'", file.path);
+
+                        return $crate::files::StaticDir::send(&$files, file, writer).await;
+                    }
+
+                    $crate::router!(@allow allowed GET);
+                }
+            )?
+
+            if !allowed.is_empty() && request_method == $crate::request::HttpMethod::Options {
+                $crate::log!(debug, "Sending allowed methods for page '{}'", path);
+
+                return $crate::routing::send_options(config, reader.request, writer, allowed).await;
+            }
+
+            if !allowed.is_empty() {
+                $crate::log!(debug, "Method not allowed for page '{}'", path);
+
+                // OPTIONS requests are always answered, see send_options
+                allowed.insert($crate::request::HttpMethod::Options);
+                let mut buf = [0u8; 64];
+                return writer
+                    .start($crate::status::StatusCode::METHOD_NOT_ALLOWED)
+                    .await?
+                    .header("Allow", allowed.as_str(&mut buf))
+                    .await?
+                    .body_empty()
+                    .await;
+            }
+
+            $crate::log!(debug, "Routing page '{}' to 404", path);
+
+            // handle 404s
+            writer
+            .static_page_or_empty(
+                config.http_404,
+                $crate::status::StatusCode::NOT_FOUND
+            ).await
             }
 
         routerfn
     }
     };
+
+    (state: $state:ty; $($routes:tt)+) => {
+        $crate::router!(@routes $state, [with_state], $($routes)+)
+    };
+    ($($routes:tt)+) => {
+        $crate::router!(@routes (), [], $($routes)+)
+    };
 }
 
 #[macro_export]
 #[cfg(feature = "http_basic_auth")]
 macro_rules! basic_auth {
     ($username:expr, $password:expr, $reader:expr) => {{
-        if let Some(head) = $reader
-            .request
-            .try_find_header(&$crate::headers::RequestHeader::Authorization)
-        {
-            use base64::Engine;
-            // TODO: make sure this doesn't panic
-            if let Some(base64) = head.strip_prefix("Basic ") {
-                let mut buf = [0u8; 64];
-                if let Ok(len) = ::base64::prelude::BASE64_STANDARD.decode_slice(base64, &mut buf) {
-                    let buf = &buf[..len];
-
-                    if let Some(colon) = buf.iter().position(|a| *a == b':') {
-                        let name = &buf[..colon];
-                        let pass = &buf[colon + 1..];
-
-                        if name == $username.as_bytes() && pass == $password.as_bytes() {
-                            $crate::log!(
-                                debug,
-                                "Authentication succeeded for path {}",
-                                $reader.request.path()
-                            );
-                            true
-                        } else {
-                            $crate::log!(
-                                debug,
-                                "Authentication failed for path {}",
-                                $reader.request.path()
-                            );
-                            false
-                        }
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                }
-            } else {
-                false
-            }
+        if $crate::auth::check_basic_auth($reader.request, $username, $password) {
+            $crate::log!(
+                debug,
+                "Authentication succeeded for path {}",
+                $reader.request.path()
+            );
+            true
         } else {
+            $crate::log!(
+                debug,
+                "Authentication failed for path {}",
+                $reader.request.path()
+            );
             false
         }
     }};
@@ -110,3 +531,85 @@ macro_rules! global_basic_auth {
 }
 
 pub(crate) use global_basic_auth;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Matches a path, returning the last captured parameter if it matches.
+    fn capture<'p>(pattern: &str, path: &'p str) -> Option<Option<&'p str>> {
+        let mut value = None;
+        let matches = match_route(pattern, path, |_, v| value = Some(v));
+
+        matches.then_some(value)
+    }
+
+    #[test]
+    fn literals() {
+        assert!(match_route("/", "/", |_, _| {}));
+        assert!(match_route("/api/led", "/api/led", |_, _| {}));
+        assert!(!match_route("/", "/a", |_, _| {}));
+        assert!(!match_route("/led", "/led/", |_, _| {}));
+        assert!(!match_route("/led/", "/led", |_, _| {}));
+        assert!(!match_route("/api/led", "/api", |_, _| {}));
+    }
+
+    #[test]
+    fn params() {
+        assert_eq!(capture("/api/{id}/value", "/api/3/value"), Some(Some("3")));
+        assert_eq!(capture("/api/{id}", "/api/a%20b"), Some(Some("a%20b")));
+        assert_eq!(capture("/api/{id}/value", "/api//value"), None);
+        assert_eq!(capture("/api/{id}", "/api/"), None);
+        assert_eq!(capture("/api/{id}", "/api/3/value"), None);
+
+        let mut count = 0;
+        assert!(match_route("/{a}/{b}", "/x/y", |name, value| {
+            assert_eq!((name, value), [("a", "x"), ("b", "y")][count]);
+            count += 1;
+        }));
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(match_route("/static/*", "/static/a/b", |_, _| {}));
+        assert!(match_route("/static/*", "/static/", |_, _| {}));
+        assert!(!match_route("/static/*", "/static", |_, _| {}));
+        assert!(!match_route("/static/*", "/other/a", |_, _| {}));
+
+        assert_eq!(capture("/files/{*rest}", "/files/a/b"), Some(Some("a/b")));
+        assert_eq!(capture("/files/{*rest}", "/files/"), Some(Some("")));
+        assert_eq!(capture("/files/{*rest}", "/files"), None);
+    }
+
+    #[test]
+    fn route_params() {
+        assert!(route_has_param("/api/{id}/value", "id"));
+        assert!(route_has_param("/{id}", "id"));
+        assert!(route_has_param("/files/{*rest}", "rest"));
+        assert!(!route_has_param("/api/{id}/value", "i"));
+        assert!(!route_has_param("/api/{ids}", "id"));
+        assert!(!route_has_param("/api/x{id}", "id"));
+        assert!(!route_has_param("/api/{id}x", "id"));
+        assert!(!route_has_param("/files/{*rest}/more", "rest"));
+        assert!(!route_has_param("/api/id", "id"));
+    }
+
+    #[test]
+    fn typed_params() {
+        assert_eq!(parse_param::<u8>("3"), Some(3));
+        assert_eq!(parse_param::<u8>("%33"), Some(3));
+        assert_eq!(parse_param::<i32>("%2D12"), Some(-12));
+        assert_eq!(parse_param::<u8>("%3"), None);
+        assert_eq!(parse_param::<u8>("256"), None);
+        // only query strings have `+` for spaces
+        assert_eq!(parse_param::<f32>("1e+3"), Some(1000.0));
+
+        // zeros that only fit once decoded
+        let mut zeros = [b'0'; MAX_PARAM_LEN + 3];
+        zeros[..3].copy_from_slice(b"%30");
+        let zeros = core::str::from_utf8(&zeros).unwrap();
+        assert_eq!(parse_param::<u8>(&zeros[..MAX_PARAM_LEN + 2]), Some(0));
+        assert_eq!(parse_param::<u8>(zeros), None);
+    }
+}
@@ -0,0 +1,340 @@
+//! Utilities to test handlers without a network, by sending raw requests to the server over an in-memory connection.
+//!
+//! The server still uses `embassy-time` for its timeouts, so a time driver is needed, like the one from its `std` feature.
+//!
+//! ```ignore
+//! let config = HttpConfig::default();
+//! let server = HttpServer::with_config(&config).route(router! {
+//!     "/" => index,
+//! });
+//!
+//! let mut response_buf = [0u8; 1024];
+//! let mut http_buf = [0u8; 2048];
+//! let response = embassy_futures::block_on(server.test_request(
+//!     b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+//!     &mut response_buf,
+//!     &mut http_buf,
+//! ))
+//! .unwrap();
+//!
+//! assert_eq!(response.status(), StatusCode::OK);
+//! assert_eq!(response.header("Content-Type"), Some("text/html; charset=UTF-8"));
+//! ```
+
+use core::{convert::Infallible, ops::AsyncFn, str};
+
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+use crate::{
+    config::HttpConfig,
+    error::Error,
+    middleware::Middleware,
+    reader::RequestReader,
+    request::HttpMethod,
+    status::StatusCode,
+    transport::{Connection, Transport},
+    writer::{HttpResponse, ResponseWriter},
+    RoutableHttpServer,
+};
+
+impl<'a, F, N, S: ?Sized, M: Middleware<S>> RoutableHttpServer<'a, F, N, S, M> {
+    /// Sends the raw `request` to the server over an in-memory connection, and parses the response.
+    ///
+    /// `request` can contain multiple pipelined requests: all the responses are written to `response_buf`,
+    /// but only the first one is parsed.
+    ///
+    /// Returns [`Error::BadRequest`] if the server didn't write a valid response.
+    pub async fn test_request<'r>(
+        &self,
+        request: &[u8],
+        response_buf: &'r mut [u8],
+        http_buf: &mut [u8],
+    ) -> Result<TestResponse<'r>, Error>
+    where
+        F: for<'c, 'd, 'e> AsyncFn(
+            &'c HttpConfig<'d>,
+            &'c S,
+            RequestReader<'c, 'd, 'e, MemoryTransport>,
+            ResponseWriter<'c, 'd, MemoryTransport>,
+        ) -> Result<HttpResponse, Error>,
+    {
+        // the response to a HEAD request has no body, even with a Content-Length
+        let method = HttpMethod::from_request_line(request);
+
+        let mut connection = MemoryConnection::new(request, response_buf);
+
+        self.handle_connection(&mut connection, http_buf).await;
+
+        let state = connection.state;
+        TestResponse::parse(
+            &mut connection.response[..connection.written],
+            method,
+            state,
+        )
+    }
+}
+
+/// The in-memory transport used by [`MemoryConnection`].
+pub enum MemoryTransport {}
+
+impl Transport for MemoryTransport {
+    type Reader<'a> = MemoryReader<'a>;
+    type Writer<'a> = MemoryWriter<'a>;
+}
+
+/// State of a [`MemoryConnection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Open,
+    /// The server closed the connection gracefully.
+    Closed,
+    /// The server aborted the connection.
+    Aborted,
+}
+
+/// A connection that reads the request from a buffer and writes the response into another one.
+pub struct MemoryConnection<'a, 'b> {
+    request: &'a [u8],
+    read: usize,
+    response: &'b mut [u8],
+    written: usize,
+    state: ConnectionState,
+}
+
+impl<'a, 'b> MemoryConnection<'a, 'b> {
+    pub fn new(request: &'a [u8], response_buf: &'b mut [u8]) -> Self {
+        Self {
+            request,
+            read: 0,
+            response: response_buf,
+            written: 0,
+            state: ConnectionState::Open,
+        }
+    }
+
+    /// The raw data written by the server.
+    pub fn response(&self) -> &[u8] {
+        &self.response[..self.written]
+    }
+
+    /// The amount of request data read by the server, in bytes.
+    pub fn read(&self) -> usize {
+        self.read
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+}
+
+impl Connection for MemoryConnection<'_, '_> {
+    type Transport = MemoryTransport;
+
+    fn split(&mut self) -> (MemoryReader<'_>, MemoryWriter<'_>) {
+        (
+            MemoryReader {
+                data: self.request,
+                pos: &mut self.read,
+            },
+            MemoryWriter {
+                buf: self.response,
+                len: &mut self.written,
+            },
+        )
+    }
+
+    async fn close(&mut self) {
+        self.state = ConnectionState::Closed;
+    }
+
+    async fn abort(&mut self) {
+        self.state = ConnectionState::Aborted;
+    }
+}
+
+/// The reading half of a [`MemoryConnection`]. It reaches the end of the stream once the request is fully read.
+pub struct MemoryReader<'a> {
+    data: &'a [u8],
+    pos: &'a mut usize,
+}
+
+impl ErrorType for MemoryReader<'_> {
+    type Error = Infallible;
+}
+
+impl Read for MemoryReader<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let rest = &self.data[*self.pos..];
+        let count = buf.len().min(rest.len());
+
+        buf[..count].copy_from_slice(&rest[..count]);
+        *self.pos += count;
+
+        Ok(count)
+    }
+}
+
+/// The writing half of a [`MemoryConnection`]. Writing fails once the response buffer is full.
+pub struct MemoryWriter<'a> {
+    buf: &'a mut [u8],
+    len: &'a mut usize,
+}
+
+impl ErrorType for MemoryWriter<'_> {
+    type Error = ErrorKind;
+}
+
+impl Write for MemoryWriter<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let rest = &mut self.buf[*self.len..];
+        if rest.is_empty() {
+            return Err(ErrorKind::OutOfMemory);
+        }
+
+        let count = buf.len().min(rest.len());
+        rest[..count].copy_from_slice(&buf[..count]);
+        *self.len += count;
+
+        Ok(count)
+    }
+
+    async fn flush(&mut self) -> Result<(), ErrorKind> {
+        Ok(())
+    }
+}
+
+/// A HTTP response parsed from the raw data written by the server.
+#[derive(Debug)]
+pub struct TestResponse<'a> {
+    status: StatusCode,
+    headers: &'a str,
+    body: &'a [u8],
+    connection: ConnectionState,
+}
+
+impl<'a> TestResponse<'a> {
+    /// Parses the first response in `raw`, sent for a request with the method `method`. Chunked bodies are decoded in place.
+    ///
+    /// Responses to HEAD requests and 1xx, 204 and 304 responses have no body.
+    ///
+    /// Returns [`Error::BadRequest`] if it isn't a valid response.
+    pub fn parse(
+        raw: &'a mut [u8],
+        method: Option<HttpMethod>,
+        connection: ConnectionState,
+    ) -> Result<Self, Error> {
+        let header_end = raw
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or(Error::BadRequest)?;
+
+        let (head, body) = raw.split_at_mut(header_end + 4);
+        let head = str::from_utf8(&head[..header_end]).map_err(|_| Error::BadRequest)?;
+
+        let (status_line, headers) = head.split_once("\r\n").unwrap_or((head, ""));
+        let mut parts = status_line.splitn(3, ' ');
+        let status = match (parts.next(), parts.next()) {
+            (Some(version), Some(code)) if version.starts_with("HTTP/") => code
+                .parse::<u16>()
+                .ok()
+                .and_then(|code| StatusCode::try_from(code).ok())
+                .ok_or(Error::BadRequest)?,
+            _ => return Err(Error::BadRequest),
+        };
+
+        let mut response = Self {
+            status,
+            headers,
+            body: &[],
+            connection,
+        };
+
+        response.body = if method == Some(HttpMethod::Head)
+            || status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+        {
+            &[]
+        } else if response
+            .header("Transfer-Encoding")
+            .is_some_and(|te| te.eq_ignore_ascii_case("chunked"))
+        {
+            decode_chunked(body)?
+        } else if let Some(len) = response.header("Content-Length") {
+            let len: usize = len.parse().map_err(|_| Error::BadRequest)?;
+
+            body.get(..len).ok_or(Error::BadRequest)?
+        } else {
+            body
+        };
+
+        Ok(response)
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Finds the value of a header, ignoring the case of its name.
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// Iterates over the names and values of the headers, in the order they were sent.
+    pub fn headers(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.headers
+            .split("\r\n")
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name, value.trim()))
+    }
+
+    pub fn body(&self) -> &'a [u8] {
+        self.body
+    }
+
+    /// The body as a string, if it's valid UTF-8.
+    pub fn body_str(&self) -> Option<&'a str> {
+        str::from_utf8(self.body).ok()
+    }
+
+    /// The state of the connection after the server was done with it.
+    pub fn connection(&self) -> ConnectionState {
+        self.connection
+    }
+}
+
+/// Decodes a chunked body in place, returning the decoded data.
+fn decode_chunked(body: &mut [u8]) -> Result<&[u8], Error> {
+    let mut read = 0;
+    let mut written = 0;
+
+    loop {
+        let line_len = body[read..]
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or(Error::BadRequest)?;
+        let line = str::from_utf8(&body[read..read + line_len]).map_err(|_| Error::BadRequest)?;
+        // skip any chunk extension
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| Error::BadRequest)?;
+        read += line_len + 2;
+
+        if size == 0 {
+            return Ok(&body[..written]);
+        }
+
+        if body.len() < read + size + 2 || &body[read + size..read + size + 2] != b"\r\n" {
+            return Err(Error::BadRequest);
+        }
+
+        body.copy_within(read..read + size, written);
+        written += size;
+        read += size + 2;
+    }
+}
@@ -0,0 +1,124 @@
+//! Abstractions over the connections the server reads requests from and writes responses to.
+//!
+//! The server works on top of anything that implements [`Listener`], like [`TcpListener`] for `embassy-net`.
+
+use embassy_net::tcp::{TcpReader, TcpSocket, TcpWriter};
+use embassy_time::Duration;
+use embedded_io_async::{ErrorKind, Read, Write};
+
+use crate::error::Error;
+
+/// The reader and writer types of a connection.
+///
+/// This is what the request readers and response writers given to handlers are generic over.
+pub trait Transport {
+    type Reader<'a>: Read;
+    type Writer<'a>: Write;
+}
+
+/// A connection to a client.
+#[allow(async_fn_in_trait)]
+pub trait Connection {
+    type Transport: Transport;
+
+    /// Splits the connection in a reading half and a writing half.
+    fn split(
+        &mut self,
+    ) -> (
+        <Self::Transport as Transport>::Reader<'_>,
+        <Self::Transport as Transport>::Writer<'_>,
+    );
+
+    /// Gracefully closes the connection, after sending any pending data.
+    async fn close(&mut self);
+
+    /// Closes the connection abruptly, discarding any pending data.
+    async fn abort(&mut self);
+}
+
+/// Waits for clients to connect.
+#[allow(async_fn_in_trait)]
+pub trait Listener {
+    type Transport: Transport;
+    type Connection<'a>: Connection<Transport = Self::Transport>
+    where
+        Self: 'a;
+
+    /// Waits for the next client.
+    async fn accept(&mut self) -> Result<Self::Connection<'_>, Error>;
+}
+
+/// The `embassy-net` TCP transport.
+pub enum EmbassyTcp {}
+
+impl Transport for EmbassyTcp {
+    type Reader<'a> = TcpReader<'a>;
+    type Writer<'a> = TcpWriter<'a>;
+}
+
+impl Connection for TcpSocket<'_> {
+    type Transport = EmbassyTcp;
+
+    fn split(&mut self) -> (TcpReader<'_>, TcpWriter<'_>) {
+        TcpSocket::split(self)
+    }
+
+    async fn close(&mut self) {
+        TcpSocket::close(self);
+        _ = TcpSocket::flush(self).await;
+    }
+
+    async fn abort(&mut self) {
+        TcpSocket::abort(self);
+        _ = TcpSocket::flush(self).await;
+    }
+}
+
+/// Listens for TCP connections on an `embassy-net` stack, one at a time.
+pub struct TcpListener<'a> {
+    network_stack: embassy_net::Stack<'a>,
+    port: u16,
+    timeout: Duration,
+    rx_buf: &'a mut [u8],
+    tx_buf: &'a mut [u8],
+}
+
+impl<'a> TcpListener<'a> {
+    /// Creates a listener on `port`, whose connections are closed after `timeout` without any data transfer.
+    pub fn new(
+        network_stack: embassy_net::Stack<'a>,
+        port: u16,
+        timeout: Duration,
+        rx_buf: &'a mut [u8],
+        tx_buf: &'a mut [u8],
+    ) -> Self {
+        Self {
+            network_stack,
+            port,
+            timeout,
+            rx_buf,
+            tx_buf,
+        }
+    }
+}
+
+impl Listener for TcpListener<'_> {
+    type Transport = EmbassyTcp;
+    type Connection<'c>
+        = TcpSocket<'c>
+    where
+        Self: 'c;
+
+    async fn accept(&mut self) -> Result<TcpSocket<'_>, Error> {
+        let mut socket = TcpSocket::new(self.network_stack, self.rx_buf, self.tx_buf);
+
+        socket.set_timeout(Some(self.timeout));
+
+        socket
+            .accept(self.port)
+            .await
+            .map_err(|_| Error::Io(ErrorKind::ConnectionAborted))?;
+
+        Ok(socket)
+    }
+}
@@ -0,0 +1,271 @@
+//! Percent-decoding of request paths and query strings, without allocations.
+
+use core::str;
+
+use crate::error::Error;
+
+/// A percent-encoded part of a URL, decoded lazily.
+#[derive(Debug, Clone, Copy)]
+pub struct Encoded<'a> {
+    raw: &'a str,
+    /// In query strings, `+` is a space.
+    plus_as_space: bool,
+}
+
+impl<'a> Encoded<'a> {
+    /// A percent-encoded path, or path segment.
+    pub const fn path(raw: &'a str) -> Self {
+        Self {
+            raw,
+            plus_as_space: false,
+        }
+    }
+
+    /// A percent-encoded query string key or value.
+    pub const fn query(raw: &'a str) -> Self {
+        Self {
+            raw,
+            plus_as_space: true,
+        }
+    }
+
+    /// Gets the data as it was sent by the client, without decoding it.
+    pub fn raw(&self) -> &'a str {
+        self.raw
+    }
+
+    /// Gets the data without copying it, if it doesn't need any decoding.
+    pub fn as_str(&self) -> Option<&'a str> {
+        let encoded = self
+            .raw
+            .bytes()
+            .any(|b| b == b'%' || (self.plus_as_space && b == b'+'));
+
+        if encoded {
+            None
+        } else {
+            Some(self.raw)
+        }
+    }
+
+    /// Iterates over the decoded bytes.
+    pub fn bytes(&self) -> DecodedBytes<'a> {
+        DecodedBytes {
+            raw: self.raw.as_bytes(),
+            pos: 0,
+            plus_as_space: self.plus_as_space,
+        }
+    }
+
+    /// Decodes the data into `buf`.
+    ///
+    /// Returns [`Error::EntityTooLarge`] if it doesn't fit, and [`Error::BadRequest`] if it isn't valid UTF-8.
+    pub fn decode_into<'b>(&self, buf: &'b mut [u8]) -> Result<&'b str, Error> {
+        let mut len = 0;
+
+        for byte in self.bytes() {
+            *buf.get_mut(len).ok_or(Error::EntityTooLarge)? = byte;
+            len += 1;
+        }
+
+        str::from_utf8(&buf[..len]).map_err(|_| Error::BadRequest)
+    }
+}
+
+impl PartialEq<&str> for Encoded<'_> {
+    /// Compares the decoded data with a string.
+    fn eq(&self, other: &&str) -> bool {
+        self.bytes().eq(other.bytes())
+    }
+}
+
+/// Iterator over the decoded bytes of an [`Encoded`] string.
+#[derive(Debug, Clone)]
+pub struct DecodedBytes<'a> {
+    raw: &'a [u8],
+    pos: usize,
+    plus_as_space: bool,
+}
+
+impl Iterator for DecodedBytes<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.pos == self.raw.len() {
+            return None;
+        }
+
+        let (byte, len) = decode_byte(self.raw, self.pos, self.plus_as_space);
+        self.pos += len;
+
+        Some(byte)
+    }
+}
+
+/// Iterator over the key/value pairs of a query string.
+///
+/// Keys without a value (`?key`) have an empty value.
+#[derive(Debug, Clone)]
+pub struct QueryParams<'a> {
+    rest: Option<&'a str>,
+}
+
+impl<'a> QueryParams<'a> {
+    pub fn new(query: &'a str) -> Self {
+        Self { rest: Some(query) }
+    }
+}
+
+impl<'a> Iterator for QueryParams<'a> {
+    type Item = (Encoded<'a>, Encoded<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (pair, rest) = match self.rest?.split_once('&') {
+                Some((pair, rest)) => (pair, Some(rest)),
+                None => (self.rest?, None),
+            };
+            self.rest = rest;
+
+            if pair.is_empty() {
+                continue;
+            }
+
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+
+            return Some((Encoded::query(key), Encoded::query(value)));
+        }
+    }
+}
+
+/// Decodes a percent-encoded path into `buf`.
+///
+/// Returns [`Error::EntityTooLarge`] if it doesn't fit, and [`Error::BadRequest`] if it isn't valid UTF-8.
+pub fn percent_decode<'b>(path: &str, buf: &'b mut [u8]) -> Result<&'b str, Error> {
+    Encoded::path(path).decode_into(buf)
+}
+
+/// Decodes a percent-encoded path in place. The decoded data is never longer than the encoded one.
+///
+/// Returns [`Error::BadRequest`] if it isn't valid UTF-8.
+pub fn percent_decode_in_place(buf: &mut [u8]) -> Result<&str, Error> {
+    let mut read = 0;
+    let mut written = 0;
+
+    while read < buf.len() {
+        let (byte, len) = decode_byte(buf, read, false);
+        buf[written] = byte;
+        read += len;
+        written += 1;
+    }
+
+    str::from_utf8(&buf[..written]).map_err(|_| Error::BadRequest)
+}
+
+/// Decodes the byte at `pos`, returning it and the amount of encoded bytes it took.
+///
+/// Invalid escapes are kept as they are.
+fn decode_byte(raw: &[u8], pos: usize, plus_as_space: bool) -> (u8, usize) {
+    match raw[pos] {
+        b'%' if pos + 2 < raw.len() => match (hex_value(raw[pos + 1]), hex_value(raw[pos + 2])) {
+            (Some(high), Some(low)) => (high << 4 | low, 3),
+            _ => (b'%', 1),
+        },
+        b'+' if plus_as_space => (b' ', 1),
+        byte => (byte, 1),
+    }
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        assert_eq!(decode_byte(b"%41", 0, false), (b'A', 3));
+        assert_eq!(decode_byte(b"%2f", 0, false), (b'/', 3));
+        assert_eq!(decode_byte(b"x%C3%A9", 1, false), (0xc3, 3));
+        assert_eq!(decode_byte(b"a%20", 1, false), (b' ', 3));
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        assert_eq!(decode_byte(b"%zz", 0, false), (b'%', 1));
+        assert_eq!(decode_byte(b"%4g", 0, false), (b'%', 1));
+        // truncated at the end of the input
+        assert_eq!(decode_byte(b"%4", 0, false), (b'%', 1));
+        assert_eq!(decode_byte(b"a%", 1, false), (b'%', 1));
+    }
+
+    #[test]
+    fn plus() {
+        assert_eq!(decode_byte(b"+", 0, true), (b' ', 1));
+        assert_eq!(decode_byte(b"+", 0, false), (b'+', 1));
+        assert_eq!(decode_byte(b"%2B", 0, true), (b'+', 3));
+    }
+
+    #[test]
+    fn query_params() {
+        let params = QueryParams::new("a=1&&flag&b=x+y%21&=empty&c=");
+        assert!(params.map(|(key, value)| (key.raw(), value.raw())).eq([
+            ("a", "1"),
+            ("flag", ""),
+            ("b", "x+y%21"),
+            ("", "empty"),
+            ("c", "")
+        ]));
+
+        let (key, value) = QueryParams::new("na+me=x+y%21").next().unwrap();
+        assert!(key == "na me");
+        assert!(value == "x y!");
+
+        assert_eq!(QueryParams::new("").count(), 0);
+        assert_eq!(QueryParams::new("&&").count(), 0);
+    }
+
+    #[test]
+    fn compare() {
+        assert!(Encoded::path("a%20b") == "a b");
+        assert!(Encoded::path("a+b") == "a+b");
+        assert!(Encoded::query("a+b") == "a b");
+        assert!(Encoded::path("a%2") == "a%2");
+        assert!(Encoded::path("a%20b") != "a%20b");
+        assert!(Encoded::path("ab") != "abc");
+
+        assert_eq!(Encoded::path("plain").as_str(), Some("plain"));
+        assert_eq!(Encoded::path("a+b").as_str(), Some("a+b"));
+        assert_eq!(Encoded::query("a+b").as_str(), None);
+        assert_eq!(Encoded::path("%41").as_str(), None);
+    }
+
+    #[test]
+    fn decode_into() {
+        let mut buf = [0u8; 4];
+        assert_eq!(Encoded::path("%41b%63d").decode_into(&mut buf), Ok("Abcd"));
+        assert_eq!(
+            Encoded::path("abcde").decode_into(&mut buf),
+            Err(Error::EntityTooLarge)
+        );
+        assert_eq!(
+            Encoded::path("%ff").decode_into(&mut buf),
+            Err(Error::BadRequest)
+        );
+        assert_eq!(percent_decode("/a%20b", &mut [0u8; 16]), Ok("/a b"));
+    }
+
+    #[test]
+    fn decode_in_place() {
+        let mut buf = *b"/my%20icon+%C3%A9.png";
+        assert_eq!(percent_decode_in_place(&mut buf), Ok("/my icon+\u{e9}.png"));
+
+        let mut buf = *b"/100%";
+        assert_eq!(percent_decode_in_place(&mut buf), Ok("/100%"));
+
+        let mut buf = *b"/%C3";
+        assert_eq!(percent_decode_in_place(&mut buf), Err(Error::BadRequest));
+    }
+}
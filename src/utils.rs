@@ -16,4 +16,23 @@ impl USizeStrBuf {
         // This never panics
         str::from_utf8(utf8).unwrap()
     }
+
+    pub fn stringify_hex(&mut self, val: usize) -> &str {
+        let utf8 = val.numtoa(16, &mut self.buf);
+        // This never panics
+        str::from_utf8(utf8).unwrap()
+    }
+}
+
+/// 64 bit FNV-1a hash, to tell versions of some data apart.
+pub const fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    let mut i = 0;
+    while i < data.len() {
+        hash = (hash ^ data[i] as u64).wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+
+    hash
 }
@@ -1,32 +1,76 @@
-use core::marker::PhantomData;
+use core::{fmt::Write as _, marker::PhantomData};
 
-use embassy_net::tcp::TcpWriter;
 use embedded_io_async::Write;
+use mr_mime::Mime;
 
 use crate::{
-    config::StaticPage, error::Error, reader::RequestReader, request::HttpVersion,
-    status::StatusCode, utils,
+    config::{ContentType, CorsConfig, StaticPage},
+    encoding::{AcceptEncoding, ContentCoding},
+    error::Error,
+    headers::HeaderName,
+    request::{HttpMethod, HttpRequest, HttpVersion},
+    status::StatusCode,
+    transport::{EmbassyTcp, Transport},
+    utils,
 };
 
+/// Maximum length of a MIME type guessed from a file extension.
+const MAX_MIME_LEN: usize = 128;
+
 /// Used to write HTTP responses.
 ///
 /// Uses typestate to make it impossible to misuse.
-pub struct HttpWriter<'a, 'b, T>
+pub struct HttpWriter<'a, 'b, S, T: Transport = EmbassyTcp>
 where
-    T:,
+    S:,
 {
-    socket: &'a mut TcpWriter<'b>,
+    socket: &'a mut T::Writer<'b>,
     version: HttpVersion,
-    marker: PhantomData<T>,
+    /// Whether the connection will be kept open after this response.
+    keep_alive: bool,
+    /// Whether the request is a HEAD request, so the body must not be sent.
+    head: bool,
+    /// The request being answered, to negotiate the response from its headers.
+    request: Option<&'a HttpRequest<'a>>,
+    /// The CORS settings and the allowed origin, if the request comes from an allowed origin.
+    cors: Option<(&'a CorsConfig<'a>, &'a str)>,
+    /// Headers added by middlewares, sent with every response.
+    extra_headers: Option<&'a ExtraHeaders<'a>>,
+    /// The status code of the response, once it has been started.
+    status: StatusCode,
+    /// Set once the status line is written, so the server knows it can't send an error page anymore.
+    started: Option<&'a mut bool>,
+    marker: PhantomData<S>,
 }
 
-pub type ResponseWriter<'a, 'b> = HttpWriter<'a, 'b, Start>;
+pub type ResponseWriter<'a, 'b, T = EmbassyTcp> = HttpWriter<'a, 'b, Start, T>;
 
 /// Http response
 ///
 /// Internally, it's just a marker to make sure that every HTTP handler function has a response.
+/// It also remembers whether the connection can be kept open after the response.
 pub struct HttpResponse {
-    _marker: (),
+    pub(crate) keep_alive: bool,
+}
+
+/// Headers that a middleware adds to every response, see [`HttpWriter::with_headers`].
+pub struct ExtraHeaders<'a> {
+    headers: &'a [(&'a str, &'a str)],
+    /// The headers that were added before these ones.
+    next: Option<&'a ExtraHeaders<'a>>,
+}
+
+impl<'a> ExtraHeaders<'a> {
+    /// Creates a list of `headers` to add to the response of `writer`, together with the ones it already has.
+    pub fn new<T: Transport>(
+        writer: &ResponseWriter<'a, '_, T>,
+        headers: &'a [(&'a str, &'a str)],
+    ) -> Self {
+        Self {
+            headers,
+            next: writer.extra_headers,
+        }
+    }
 }
 
 pub enum Start {}
@@ -36,6 +80,24 @@ pub trait HttpStage {}
 impl HttpStage for Start {}
 impl HttpStage for Headers {}
 
+impl<'a, S, T: Transport> HttpWriter<'a, '_, S, T> {
+    /// The method of the request, if it could be parsed.
+    fn method(&self) -> Option<HttpMethod> {
+        self.request.map(HttpRequest::method)
+    }
+
+    fn request_header(&self, name: &HeaderName) -> Option<&'a str> {
+        self.request?.try_find_header(name)
+    }
+
+    /// The content codings accepted by the client, to pick the variant of static pages.
+    fn accept_encoding(&self) -> AcceptEncoding {
+        self.request
+            .map(AcceptEncoding::from_request)
+            .unwrap_or_default()
+    }
+}
+
 macro_rules! static_page {
     ($writer:expr, $page:expr, $code:expr $(,($name:expr, $value:expr))*) => {
         async {
@@ -76,30 +138,67 @@ macro_rules! static_or_empty_page {
 pub(crate) use static_or_empty_page;
 pub(crate) use static_page;
 
-impl<'a, 'b> HttpWriter<'a, 'b, Start> {
+impl<'a, 'b, T: Transport> HttpWriter<'a, 'b, Start, T> {
     /// Creates a new HTTP writer with the HTTP version requested by the client.
+    ///
+    /// If `keep_alive` is false, the response tells the client that the connection will be closed.
+    ///
+    /// If the request is a HEAD request, the body of the response is never sent.
+    ///
+    /// If `cors` is set and it allows the origin of the request, the CORS headers are added to the response.
+    ///
+    /// `started` is set to true once the response is started.
     pub(crate) fn new(
-        socket: &'a mut TcpWriter<'b>,
-        reader: &RequestReader,
-    ) -> HttpWriter<'a, 'b, Start> {
+        socket: &'a mut T::Writer<'b>,
+        request: &'a HttpRequest<'a>,
+        keep_alive: bool,
+        cors: Option<&'a CorsConfig<'a>>,
+        started: &'a mut bool,
+    ) -> HttpWriter<'a, 'b, Start, T> {
         HttpWriter {
             socket,
-            version: reader.request.version(),
+            version: request.version(),
+            keep_alive,
+            head: request.method() == HttpMethod::Head,
+            request: Some(request),
+            cors: cors.and_then(|cors| Some((cors, cors.allow_origin(request)?))),
+            extra_headers: None,
+            status: StatusCode::default(),
+            started: Some(started),
             marker: PhantomData,
         }
     }
 
-    /// Creates a new HTTP writer, forcing HTTP/1.1
-    pub(crate) fn new_http_11(socket: &'a mut TcpWriter<'b>) -> HttpWriter<'a, 'b, Start> {
+    /// Creates a new HTTP writer, forcing HTTP/1.1 and closing the connection afterwards.
+    ///
+    /// `method` is the method of the request, if it's known, so that HEAD requests don't get a body.
+    pub(crate) fn new_http_11(
+        socket: &'a mut T::Writer<'b>,
+        method: Option<HttpMethod>,
+    ) -> HttpWriter<'a, 'b, Start, T> {
         HttpWriter {
             socket,
             version: HttpVersion::Http11,
+            keep_alive: false,
+            head: method == Some(HttpMethod::Head),
+            request: None,
+            cors: None,
+            extra_headers: None,
+            status: StatusCode::default(),
+            started: None,
             marker: PhantomData,
         }
     }
 
     /// Starts a HTTP response, with the specified status code.
-    pub async fn start(self, code: StatusCode) -> Result<HttpWriter<'a, 'b, Headers>, Error> {
+    pub async fn start(
+        mut self,
+        code: StatusCode,
+    ) -> Result<HttpWriter<'a, 'b, Headers, T>, Error> {
+        if let Some(started) = self.started.as_deref_mut() {
+            *started = true;
+        }
+
         match self.version {
             HttpVersion::Http10 => self.socket.write_all(b"HTTP/1.0 ").await?,
             HttpVersion::Http11 => self.socket.write_all(b"HTTP/1.1 ").await?,
@@ -112,17 +211,174 @@ impl<'a, 'b> HttpWriter<'a, 'b, Start> {
         Ok(HttpWriter {
             socket: self.socket,
             version: self.version,
+            keep_alive: self.keep_alive,
+            head: self.head,
+            request: self.request,
+            cors: self.cors,
+            extra_headers: self.extra_headers,
+            status: code,
+            started: self.started,
             marker: PhantomData,
         })
     }
 
-    /// Serves a static page
+    /// Adds headers to the response, whatever the handler sends.
+    ///
+    /// Create `headers` with [`ExtraHeaders::new`], to keep the ones that were already added.
+    pub fn with_headers(mut self, headers: &'a ExtraHeaders<'a>) -> Self {
+        self.extra_headers = Some(headers);
+        self
+    }
+
+    /// Serves a static page.
+    ///
+    /// If the page has an ETag and `code` is a success, the `If-Match` and `If-None-Match` headers of the request are checked:
+    /// clients that already have the page get a 304 Not Modified without the body,
+    /// and requests whose condition fails get a 412 Precondition Failed.
+    ///
+    /// If `code` is 200 OK, clients can ask for a part of the page, see [`HttpWriter::ranged_bytes`].
     pub async fn static_page(
         self,
         page: StaticPage<'a>,
         code: StatusCode,
     ) -> Result<HttpResponse, Error> {
-        static_page!(self, page, code)
+        let (coding, body) = page.encoded(&self.accept_encoding());
+        let etag = page.etag(coding);
+
+        if let Some(etag) = etag.as_deref().filter(|_| code.is_success()) {
+            match self.precondition(etag) {
+                Some(StatusCode::NOT_MODIFIED) => {
+                    return self
+                        .start(StatusCode::NOT_MODIFIED)
+                        .await?
+                        .static_page_headers(&page, coding)
+                        .await?
+                        .body_empty()
+                        .await;
+                }
+                Some(code) => return self.start(code).await?.body_empty().await,
+                None => {}
+            }
+        }
+
+        if code != StatusCode::OK {
+            return static_page!(self, page, code);
+        }
+
+        let Ok(range) = self.requested_range(body.len(), etag.as_deref()) else {
+            return self.range_not_satisfiable(body.len()).await;
+        };
+
+        let (writer, body) = self.start_range(body, range).await?;
+        writer.body_static_variant(&page, coding, body).await
+    }
+
+    /// Sends a body with a 200 OK, or only the part of it asked by the `Range` header of the request
+    /// with a 206 Partial Content.
+    ///
+    /// Only single byte ranges are supported, like `bytes=0-499`, `bytes=500-` or `bytes=-500`.
+    /// Other ranges are ignored, and the whole body is sent.
+    /// Ranges outside of the body get a 416 Range Not Satisfiable.
+    pub async fn ranged_bytes(
+        self,
+        body: &[u8],
+        content_type: &str,
+    ) -> Result<HttpResponse, Error> {
+        let Ok(range) = self.requested_range(body.len(), None) else {
+            return self.range_not_satisfiable(body.len()).await;
+        };
+
+        let (writer, body) = self.start_range(body, range).await?;
+        writer.body_bytes(body, content_type).await
+    }
+
+    /// Finds the range of a body of `len` bytes asked by the client, as the first and last byte of the range.
+    ///
+    /// The range is only honored for GET requests, and if the `If-Range` header, if any, matches the ETag of the body.
+    fn requested_range(
+        &self,
+        len: usize,
+        etag: Option<&str>,
+    ) -> Result<Option<(usize, usize)>, Error> {
+        let range = self.request_header(&HeaderName::Range);
+        let Some(range) = range.filter(|_| self.method() == Some(HttpMethod::Get)) else {
+            return Ok(None);
+        };
+
+        // the client only wants a part of the body it already has, dates can't be checked without a Last-Modified
+        if let Some(if_range) = self.request_header(&HeaderName::IfRange) {
+            if etag != Some(if_range.trim()) {
+                return Ok(None);
+            }
+        }
+
+        parse_range(range, len)
+    }
+
+    /// Starts a 200 OK response, or a 206 Partial Content for `range`, returning the part of `body` to send.
+    async fn start_range<'p>(
+        self,
+        body: &'p [u8],
+        range: Option<(usize, usize)>,
+    ) -> Result<(HttpWriter<'a, 'b, Headers, T>, &'p [u8]), Error> {
+        let Some((first, last)) = range else {
+            let writer = self
+                .start(StatusCode::OK)
+                .await?
+                .header("Accept-Ranges", "bytes")
+                .await?;
+
+            return Ok((writer, body));
+        };
+
+        let mut content_range = heapless::String::<64>::new();
+        // can't fail, the numbers are at most 20 digits long
+        _ = write!(content_range, "bytes {first}-{last}/{}", body.len());
+
+        let writer = self
+            .start(StatusCode::PARTIAL_CONTENT)
+            .await?
+            .header("Accept-Ranges", "bytes")
+            .await?
+            .header("Content-Range", &content_range)
+            .await?;
+
+        Ok((writer, body.get(first..=last).unwrap_or_default()))
+    }
+
+    /// Tells the client that the range it asked for is outside of the body of `len` bytes.
+    async fn range_not_satisfiable(self, len: usize) -> Result<HttpResponse, Error> {
+        let mut buf = utils::USizeStrBuf::new();
+
+        self.start(StatusCode::RANGE_NOT_SATISFIABLE)
+            .await?
+            .header("Accept-Ranges", "bytes")
+            .await?
+            .header_parts("Content-Range", &["bytes */", buf.stringify(len)])
+            .await?
+            .body_empty()
+            .await
+    }
+
+    /// Checks the conditions of the request against the ETag of a page,
+    /// returning the status code to send instead of the page if they fail.
+    fn precondition(&self, etag: &str) -> Option<StatusCode> {
+        if let Some(if_match) = self.request_header(&HeaderName::IfMatch) {
+            if !etag_matches(if_match, etag, false) {
+                return Some(StatusCode::PRECONDITION_FAILED);
+            }
+        }
+
+        if let Some(if_none_match) = self.request_header(&HeaderName::IfNoneMatch) {
+            if etag_matches(if_none_match, etag, true) {
+                return Some(match self.method() {
+                    Some(HttpMethod::Get | HttpMethod::Head) => StatusCode::NOT_MODIFIED,
+                    _ => StatusCode::PRECONDITION_FAILED,
+                });
+            }
+        }
+
+        None
     }
 
     /// Serves a static page (or empty).
@@ -135,30 +391,164 @@ impl<'a, 'b> HttpWriter<'a, 'b, Start> {
     }
 }
 
-impl<'a, 'b> HttpWriter<'a, 'b, Headers> {
-    pub async fn header(self, name: &str, value: &str) -> Result<Self, Error> {
+impl<'a, 'b, T: Transport> HttpWriter<'a, 'b, Headers, T> {
+    pub async fn header(mut self, name: &str, value: &str) -> Result<Self, Error> {
+        self.write_header(name, value).await?;
+
+        Ok(self)
+    }
+
+    /// Sends a header whose value is made of multiple parts, without having to join them first.
+    pub(crate) async fn header_parts(self, name: &str, parts: &[&str]) -> Result<Self, Error> {
         self.socket.write_all(name.as_bytes()).await?;
         self.socket.write_all(b": ").await?;
-        self.socket.write_all(value.as_bytes()).await?;
+        for part in parts {
+            self.socket.write_all(part.as_bytes()).await?;
+        }
         self.socket.write_all(b"\r\n").await?;
 
         Ok(self)
     }
 
-    pub async fn body_empty(self) -> Result<HttpResponse, Error> {
+    async fn write_header(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        self.socket.write_all(name.as_bytes()).await?;
+        self.socket.write_all(b": ").await?;
+        self.socket.write_all(value.as_bytes()).await?;
+        self.socket.write_all(b"\r\n").await?;
+
+        Ok(())
+    }
+
+    /// Sends the `Connection`, CORS and extra headers, followed by the newline that starts the body section.
+    async fn end_headers(&mut self) -> Result<(), Error> {
+        let mut extra = self.extra_headers;
+        while let Some(headers) = extra {
+            for (name, value) in headers.headers {
+                self.write_header(name, value).await?;
+            }
+            extra = headers.next;
+        }
+
+        if let Some((cors, origin)) = self.cors {
+            self.write_header("Access-Control-Allow-Origin", origin)
+                .await?;
+            if origin != "*" {
+                // the response depends on the origin, so caches must not reuse it for other ones
+                self.write_header("Vary", "Origin").await?;
+            }
+            if cors.allow_credentials {
+                self.write_header("Access-Control-Allow-Credentials", "true")
+                    .await?;
+            }
+            if let Some(exposed) = cors.exposed_headers {
+                self.write_header("Access-Control-Expose-Headers", exposed)
+                    .await?;
+            }
+        }
+
+        if self.keep_alive {
+            self.socket.write_all(b"Connection: keep-alive\r\n").await?;
+        } else {
+            self.socket.write_all(b"Connection: close\r\n").await?;
+        }
+
         self.socket.write_all(b"\r\n").await?;
 
-        Ok(HttpResponse { _marker: () })
+        Ok(())
+    }
+
+    pub async fn body_empty(mut self) -> Result<HttpResponse, Error> {
+        // without a length, the client would wait for the connection to close to find the end of the body
+        if !(self.status.is_informational()
+            || self.status == StatusCode::NO_CONTENT
+            || self.status == StatusCode::NOT_MODIFIED)
+        {
+            self.socket.write_all(b"Content-Length: 0\r\n").await?;
+        }
+
+        self.end_headers().await?;
+
+        Ok(HttpResponse {
+            keep_alive: self.keep_alive,
+        })
     }
 
     pub async fn body_str(self, body: &str, content_type: &str) -> Result<HttpResponse, Error> {
         self.body_bytes(body.as_bytes(), content_type).await
     }
 
+    /// Sends a static page, using the best compressed variant the client accepts.
+    ///
+    /// The status code is already sent, so unlike [`HttpWriter::static_page`], the `If-Match`, `If-None-Match`
+    /// and `Range` headers of the request are ignored, and the whole page is sent.
     pub async fn body_static_page(self, page: StaticPage<'a>) -> Result<HttpResponse, Error> {
-        self.body_str(page.body, page.content_type).await
+        let (coding, body) = page.encoded(&self.accept_encoding());
+
+        self.body_static_variant(&page, coding, body).await
+    }
+
+    /// Sends `body`, which is a part of the variant of a static page encoded with `coding`.
+    async fn body_static_variant(
+        mut self,
+        page: &StaticPage<'a>,
+        coding: ContentCoding,
+        body: &[u8],
+    ) -> Result<HttpResponse, Error> {
+        let mut mime = heapless::String::<MAX_MIME_LEN>::new();
+        let content_type = match page.content_type {
+            ContentType::Str(content_type) => content_type,
+            ContentType::Extension(extension) => match Mime::guess(extension).next() {
+                Some(m) => {
+                    let written = write!(mime, "{m}").and_then(|()| {
+                        // text without a charset isn't decoded as UTF-8 by every browser
+                        if mime.starts_with("text/") {
+                            mime.write_str("; charset=UTF-8")
+                        } else {
+                            Ok(())
+                        }
+                    });
+
+                    match written {
+                        Ok(()) => &mime,
+                        // no known MIME type is this long, but just in case
+                        Err(_) => "application/octet-stream",
+                    }
+                }
+                None => "application/octet-stream",
+            },
+        };
+
+        self = self.static_page_headers(page, coding).await?;
+        if coding != ContentCoding::Identity {
+            self = self.header("Content-Encoding", coding.as_str()).await?;
+        }
+
+        self.body_bytes(body, content_type).await
     }
 
+    /// Sends the headers that describe the variant of a static page sent with `coding`.
+    async fn static_page_headers(
+        mut self,
+        page: &StaticPage<'a>,
+        coding: ContentCoding,
+    ) -> Result<Self, Error> {
+        // the body depends on the encodings the client accepts, caches have to know it
+        if page.is_compressed() {
+            self = self.header("Vary", "Accept-Encoding").await?;
+        }
+        if let Some(etag) = page.etag(coding) {
+            self = self.header("ETag", &etag).await?;
+        }
+        if let Some(cache_control) = page.cache_control {
+            self = self.header("Cache-Control", cache_control).await?;
+        }
+
+        Ok(self)
+    }
+
+    /// Sends the body, with its length.
+    ///
+    /// For HEAD requests, only the headers are sent.
     pub async fn body_bytes(
         mut self,
         body: &[u8],
@@ -171,18 +561,24 @@ impl<'a, 'b> HttpWriter<'a, 'b, Headers> {
             .header("Content-Length", buf.stringify(body.len()))
             .await?;
 
-        // send newline to go to body section
-        self.socket.write_all(b"\r\n").await?;
-        self.socket.write_all(body).await?;
+        self.end_headers().await?;
+        if !self.head {
+            self.socket.write_all(body).await?;
+        }
 
-        Ok(HttpResponse { _marker: () })
+        Ok(HttpResponse {
+            keep_alive: self.keep_alive,
+        })
     }
 
+    /// Starts a body of `length` bytes, that is sent in chunks, like a file streamed from flash.
+    ///
+    /// For HEAD requests, only the headers are sent and the chunks are discarded.
     pub async fn body_chunked(
         mut self,
         length: usize,
         content_type: &str,
-    ) -> Result<ChunkedHttpWriter<'a, 'b>, Error> {
+    ) -> Result<ChunkedHttpWriter<'a, 'b, T>, Error> {
         let mut buf = utils::USizeStrBuf::new();
         self = self
             .header("Content-Type", content_type)
@@ -190,29 +586,133 @@ impl<'a, 'b> HttpWriter<'a, 'b, Headers> {
             .header("Content-Length", buf.stringify(length))
             .await?;
 
-        // send newline to go to body section
-        self.socket.write_all(b"\r\n").await?;
+        self.end_headers().await?;
 
         Ok(ChunkedHttpWriter {
             socket: self.socket,
+            head: self.head,
+            keep_alive: self.keep_alive,
             total: length,
             written: 0,
         })
     }
+
+    /// Starts a body whose length isn't known in advance, using chunked transfer encoding.
+    ///
+    /// HTTP/1.0 clients don't support it, so they get the raw body and the connection is closed at the end of it.
+    ///
+    /// For HEAD requests, only the headers are sent and the chunks are discarded.
+    pub async fn body_chunked_encoding(
+        mut self,
+        content_type: &str,
+    ) -> Result<ChunkedEncodingWriter<'a, 'b, T>, Error> {
+        let chunked = self.version >= HttpVersion::Http11;
+
+        self = self.header("Content-Type", content_type).await?;
+
+        if chunked {
+            self = self.header("Transfer-Encoding", "chunked").await?;
+        } else if !self.head {
+            // the end of the body is marked by closing the connection
+            self.keep_alive = false;
+        }
+
+        self.end_headers().await?;
+
+        Ok(ChunkedEncodingWriter {
+            socket: self.socket,
+            chunked,
+            head: self.head,
+            keep_alive: self.keep_alive,
+            written: 0,
+        })
+    }
+}
+
+/// Parses a `Range` header for a body of `len` bytes, returning the first and last byte of the range.
+///
+/// Returns [`None`] if the header has to be ignored, because it's invalid or asks for multiple ranges,
+/// or [`Error::OutOfRange`] if the range is outside of the body.
+fn parse_range(header: &str, len: usize) -> Result<Option<(usize, usize)>, Error> {
+    let Some((unit, range)) = header.split_once('=') else {
+        return Ok(None);
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") || range.contains(',') {
+        return Ok(None);
+    }
+    let Some((first, last)) = range.split_once('-') else {
+        return Ok(None);
+    };
+
+    let position = |s: &str| {
+        let s = s.trim();
+        // positions past the end of any body are clamped or rejected like the others
+        (!s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| s.parse::<usize>().unwrap_or(usize::MAX))
+    };
+
+    match (position(first), position(last)) {
+        // the last bytes of the body
+        (None, Some(suffix)) if first.trim().is_empty() => {
+            if suffix == 0 || len == 0 {
+                return Err(Error::OutOfRange);
+            }
+
+            Ok(Some((len.saturating_sub(suffix), len - 1)))
+        }
+        (Some(first), last) if last.is_some() || range.trim_end().ends_with('-') => {
+            // an invalid range is ignored, even if it starts past the end of the body
+            if last.is_some_and(|last| last < first) {
+                return Ok(None);
+            }
+            if first >= len {
+                return Err(Error::OutOfRange);
+            }
+
+            Ok(Some((
+                first,
+                last.map_or(len - 1, |last| last.min(len - 1)),
+            )))
+        }
+        _ => Ok(None),
+    }
 }
 
-pub struct ChunkedHttpWriter<'a, 'b> {
-    socket: &'a mut TcpWriter<'b>,
+/// Checks whether `etag` is one of the entity tags of an `If-Match` or `If-None-Match` header.
+///
+/// Weak entity tags only match with the weak comparison.
+fn etag_matches(tags: &str, etag: &str, weak: bool) -> bool {
+    tags.trim() == "*"
+        || tags
+            .split(',')
+            .map(str::trim)
+            .any(|tag| match tag.strip_prefix("W/") {
+                Some(tag) => weak && tag == etag,
+                None => tag == etag,
+            })
+}
+
+/// Used to write a HTTP response body of a known length in chunks.
+pub struct ChunkedHttpWriter<'a, 'b, T: Transport = EmbassyTcp> {
+    socket: &'a mut T::Writer<'b>,
+    /// Whether the request is a HEAD request, so the chunks are discarded.
+    head: bool,
+    keep_alive: bool,
     total: usize,
     written: usize,
 }
 
-impl<'a, 'b> ChunkedHttpWriter<'a, 'b> {
+impl<'a, 'b, T: Transport> ChunkedHttpWriter<'a, 'b, T> {
+    /// Writes a chunk of the body. Returns the response once the whole body was written.
     pub async fn write_chunk(&mut self, chunk: &[u8]) -> Result<Option<HttpResponse>, Error> {
         if self.written == self.total {
-            return Ok(Some(HttpResponse { _marker: () }));
+            return Ok(Some(HttpResponse {
+                keep_alive: self.keep_alive,
+            }));
+        }
+        if !self.head {
+            self.socket.write_all(chunk).await?;
         }
-        self.socket.write_all(chunk).await?;
         self.written += chunk.len();
         // TODO: implement Drop to send a RST packet here
         // is it needed?
@@ -232,3 +732,120 @@ impl<'a, 'b> ChunkedHttpWriter<'a, 'b> {
         self.written
     }
 }
+
+/// Used to write a HTTP response body in chunks, without knowing its length in advance.
+///
+/// Call [`ChunkedEncodingWriter::finish`] after the last chunk to end the response.
+pub struct ChunkedEncodingWriter<'a, 'b, T: Transport = EmbassyTcp> {
+    socket: &'a mut T::Writer<'b>,
+    /// Whether chunked transfer encoding is used, or the body is delimited by closing the connection.
+    chunked: bool,
+    /// Whether the request is a HEAD request, so the chunks are discarded.
+    head: bool,
+    keep_alive: bool,
+    written: usize,
+}
+
+impl<'a, 'b, T: Transport> ChunkedEncodingWriter<'a, 'b, T> {
+    pub async fn write_chunk(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        // an empty chunk would end the body
+        if chunk.is_empty() {
+            return Ok(());
+        }
+
+        if self.head {
+            // the response to a HEAD request has no body, but the handler doesn't need to know
+            self.written += chunk.len();
+            return Ok(());
+        }
+
+        if self.chunked {
+            let mut buf = utils::USizeStrBuf::new();
+            self.socket
+                .write_all(buf.stringify_hex(chunk.len()).as_bytes())
+                .await?;
+            self.socket.write_all(b"\r\n").await?;
+            self.socket.write_all(chunk).await?;
+            self.socket.write_all(b"\r\n").await?;
+        } else {
+            self.socket.write_all(chunk).await?;
+        }
+        self.written += chunk.len();
+
+        Ok(())
+    }
+
+    pub async fn write_chunk_str(&mut self, chunk: &str) -> Result<(), Error> {
+        self.write_chunk(chunk.as_bytes()).await
+    }
+
+    /// Ends the body, sending the last chunk.
+    pub async fn finish(self) -> Result<HttpResponse, Error> {
+        if self.chunked && !self.head {
+            self.socket.write_all(b"0\r\n\r\n").await?;
+        }
+
+        Ok(HttpResponse {
+            keep_alive: self.keep_alive,
+        })
+    }
+
+    /// The amount of body data written so far, in bytes.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("bytes=0-499", 1000), Ok(Some((0, 499))));
+        assert_eq!(parse_range("bytes=500-", 1000), Ok(Some((500, 999))));
+        assert_eq!(parse_range("bytes=-200", 1000), Ok(Some((800, 999))));
+        assert_eq!(parse_range("Bytes = 1 - 2", 1000), Ok(Some((1, 2))));
+        assert_eq!(parse_range("bytes=0-0", 1), Ok(Some((0, 0))));
+    }
+
+    #[test]
+    fn clamped_ranges() {
+        assert_eq!(parse_range("bytes=990-2000", 1000), Ok(Some((990, 999))));
+        assert_eq!(parse_range("bytes=-2000", 1000), Ok(Some((0, 999))));
+        assert_eq!(
+            parse_range("bytes=0-99999999999999999999999", 10),
+            Ok(Some((0, 9)))
+        );
+        assert_eq!(
+            parse_range("bytes=-99999999999999999999999", 10),
+            Ok(Some((0, 9)))
+        );
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(Error::OutOfRange));
+        assert_eq!(parse_range("bytes=1000-1001", 1000), Err(Error::OutOfRange));
+        assert_eq!(parse_range("bytes=-0", 1000), Err(Error::OutOfRange));
+        assert_eq!(parse_range("bytes=-5", 0), Err(Error::OutOfRange));
+        assert_eq!(parse_range("bytes=0-", 0), Err(Error::OutOfRange));
+        assert_eq!(
+            parse_range("bytes=99999999999999999999999-", 10),
+            Err(Error::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn ignored_ranges() {
+        assert_eq!(parse_range("bytes=0-1,3-4", 10), Ok(None));
+        assert_eq!(parse_range("bytes=4-2", 10), Ok(None));
+        assert_eq!(parse_range("bytes=20-10", 10), Ok(None));
+        assert_eq!(parse_range("items=0-1", 10), Ok(None));
+        assert_eq!(parse_range("bytes=a-b", 10), Ok(None));
+        assert_eq!(parse_range("bytes=-", 10), Ok(None));
+        assert_eq!(parse_range("bytes=+1-2", 10), Ok(None));
+        assert_eq!(parse_range("bytes=1", 10), Ok(None));
+        assert_eq!(parse_range("bytes 0-1", 10), Ok(None));
+    }
+}
@@ -0,0 +1,39 @@
+//! Helpers shared by the integration tests.
+
+use tinyhttp::config::HttpConfig;
+use tinyhttp::error::Error;
+use tinyhttp::middleware::Middleware;
+use tinyhttp::reader::RequestReader;
+use tinyhttp::testing::{MemoryTransport, TestResponse};
+use tinyhttp::writer::{HttpResponse, ResponseWriter};
+use tinyhttp::RoutableHttpServer;
+
+/// Sends the raw `request` to `server` and passes the first response to `check`.
+///
+/// Returns everything the server wrote, for requests that get multiple responses.
+/// A chunked body in the first response has already been decoded in place by then.
+pub fn send<F, N, S: ?Sized, M: Middleware<S>>(
+    server: &RoutableHttpServer<'_, F, N, S, M>,
+    request: &[u8],
+    check: impl FnOnce(TestResponse),
+) -> String
+where
+    F: for<'c, 'd, 'e> AsyncFn(
+        &'c HttpConfig<'d>,
+        &'c S,
+        RequestReader<'c, 'd, 'e, MemoryTransport>,
+        ResponseWriter<'c, 'd, MemoryTransport>,
+    ) -> Result<HttpResponse, Error>,
+{
+    let mut response_buf = [0u8; 4096];
+    let mut http_buf = [0u8; 1024];
+
+    check(
+        embassy_futures::block_on(server.test_request(request, &mut response_buf, &mut http_buf))
+            .unwrap(),
+    );
+
+    String::from_utf8_lossy(&response_buf)
+        .trim_end_matches('\0')
+        .to_string()
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use rand_core::RngCore;
+use tinyhttp::config::HttpConfig;
+use tinyhttp::digest_auth::{Algorithm, DigestAuth};
+use tinyhttp::error::Error;
+use tinyhttp::reader::RequestReader;
+use tinyhttp::status::StatusCode;
+use tinyhttp::testing::TestResponse;
+use tinyhttp::transport::Transport;
+use tinyhttp::writer::{HttpResponse, ResponseWriter};
+use tinyhttp::{router, HttpServer};
+
+/// Fills nonces with the same byte, incremented every time.
+struct Counter(u8);
+
+impl RngCore for Counter {
+    fn next_u32(&mut self) -> u32 {
+        unimplemented!()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        unimplemented!()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0 += 1;
+        dest.fill(self.0);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+static DIGEST: DigestAuth<CriticalSectionRawMutex, Counter, [(&str, &str)], 2> =
+    DigestAuth::new("area", &[("bob", "hunter2")], Counter(0));
+
+async fn user<T: Transport>(
+    reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    writer
+        .start(StatusCode::OK)
+        .await?
+        .body_str(reader.user().unwrap_or_default(), "text/plain")
+        .await
+}
+
+fn request(request: &str, check: impl FnOnce(TestResponse)) {
+    let config = HttpConfig::default();
+    let server = HttpServer::with_config(&config).route(router! {
+        GET "/a" => user [auth = DIGEST],
+    });
+
+    common::send(&server, request.as_bytes(), check);
+}
+
+fn hex_digest(algorithm: Algorithm, parts: &[&str]) -> String {
+    let parts: Vec<&[u8]> = parts.iter().map(|part| part.as_bytes()).collect();
+    let mut out = [0u8; 64];
+
+    String::from_utf8(algorithm.hex_digest(&parts, &mut out).to_vec()).unwrap()
+}
+
+/// Builds a request for `/a?x=1` answering the challenge with `nonce`, like a client would.
+fn authorized(algorithm: Algorithm, nonce: &str, nc: &str, password: &str) -> String {
+    let ha1 = hex_digest(algorithm, &["bob", "area", password]);
+    let ha2 = hex_digest(algorithm, &["GET", "/a?x=1"]);
+    let response = hex_digest(algorithm, &[&ha1, nonce, nc, "c", "auth", &ha2]);
+    let algorithm = match algorithm {
+        Algorithm::Md5 => "MD5",
+        Algorithm::Sha256 => "SHA-256",
+    };
+
+    format!(
+        "GET /a?x=1 HTTP/1.1\r\nAuthorization: Digest username=\"bob\", realm=\"area\", uri=\"/a?x=1\", \
+        algorithm={algorithm}, nonce=\"{nonce}\", nc={nc}, cnonce=\"c\", qop=auth, response=\"{response}\"\r\n\r\n"
+    )
+}
+
+/// Gets the `WWW-Authenticate` headers of a 401 response.
+fn challenges(response: &TestResponse) -> Vec<String> {
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    response
+        .headers()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("WWW-Authenticate"))
+        .map(|(_, value)| value.to_string())
+        .collect()
+}
+
+fn nonce_of(challenge: &str) -> String {
+    let (_, nonce) = challenge.split_once("nonce=\"").unwrap();
+    nonce.split('"').next().unwrap().to_string()
+}
+
+#[test]
+fn digest_auth() {
+    let mut nonce = String::new();
+    request("GET /a?x=1 HTTP/1.1\r\n\r\n", |response| {
+        let challenges = challenges(&response);
+        assert_eq!(challenges.len(), 2);
+        // the preferred algorithm goes first
+        assert!(
+            challenges[0].starts_with("Digest realm=\"area\", qop=\"auth\", algorithm=SHA-256"),
+            "{challenges:?}"
+        );
+        assert!(challenges[1].contains("algorithm=MD5"), "{challenges:?}");
+        nonce = nonce_of(&challenges[0]);
+    });
+    assert_eq!(nonce.len(), 32);
+
+    for (algorithm, nc) in [
+        (Algorithm::Sha256, "00000001"),
+        (Algorithm::Md5, "00000002"),
+    ] {
+        request(&authorized(algorithm, &nonce, nc, "hunter2"), |response| {
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.body_str(), Some("bob"));
+        });
+    }
+
+    // replayed requests have valid credentials, the client can retry them with a new nonce
+    let replayed = authorized(Algorithm::Sha256, &nonce, "00000002", "hunter2");
+    request(&replayed, |response| {
+        let challenges = challenges(&response);
+        assert!(challenges[0].ends_with(", stale=true"), "{challenges:?}");
+        assert_ne!(nonce_of(&challenges[0]), nonce);
+    });
+
+    // clients without the password are never told that the nonce is stale
+    let wrong = authorized(Algorithm::Sha256, &nonce, "00000003", "hunter3");
+    request(&wrong, |response| {
+        let challenges = challenges(&response);
+        assert!(!challenges[0].contains("stale"), "{challenges:?}");
+    });
+    let unknown = authorized(Algorithm::Sha256, &"ab".repeat(16), "00000001", "hunter3");
+    request(&unknown, |response| {
+        let challenges = challenges(&response);
+        assert!(!challenges[0].contains("stale"), "{challenges:?}");
+    });
+
+    // the nonce was evicted by the newer ones
+    request(
+        &authorized(Algorithm::Sha256, &nonce, "00000004", "hunter2"),
+        |response| {
+            let challenges = challenges(&response);
+            assert!(challenges[0].ends_with(", stale=true"), "{challenges:?}");
+        },
+    );
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use std::sync::Mutex;
+
+use tinyhttp::config::HttpConfig;
+use tinyhttp::error::Error;
+use tinyhttp::middleware::{Middleware, Next};
+use tinyhttp::reader::RequestReader;
+use tinyhttp::status::StatusCode;
+use tinyhttp::transport::Transport;
+use tinyhttp::writer::{ExtraHeaders, HttpResponse, ResponseWriter};
+use tinyhttp::{router, HttpServer};
+
+use common::send;
+
+/// The middlewares and handlers that ran, in order.
+static LOG: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+
+async fn hello<T: Transport>(
+    _reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    LOG.lock().unwrap().push("handler");
+
+    writer
+        .start(StatusCode::OK)
+        .await?
+        .body_str("hello", "text/plain")
+        .await
+}
+
+/// Logs that it ran, and adds its name to the response in a header.
+struct Tag(&'static str);
+
+impl<S: ?Sized> Middleware<S> for Tag {
+    async fn handle<'c, 'd, 'e, T: Transport>(
+        &self,
+        config: &'c HttpConfig<'d>,
+        state: &'c S,
+        reader: RequestReader<'c, 'd, 'e, T>,
+        writer: ResponseWriter<'c, 'd, T>,
+        next: &impl Next<S, T>,
+    ) -> Result<HttpResponse, Error> {
+        LOG.lock().unwrap().push(self.0);
+
+        let headers = [(self.0, "yes")];
+        let headers = ExtraHeaders::new(&writer, &headers);
+        next.run(config, state, reader, writer.with_headers(&headers))
+            .await
+    }
+}
+
+/// Answers requests for `/private` by itself.
+struct Deny;
+
+impl<S: ?Sized> Middleware<S> for Deny {
+    async fn handle<'c, 'd, 'e, T: Transport>(
+        &self,
+        config: &'c HttpConfig<'d>,
+        state: &'c S,
+        reader: RequestReader<'c, 'd, 'e, T>,
+        writer: ResponseWriter<'c, 'd, T>,
+        next: &impl Next<S, T>,
+    ) -> Result<HttpResponse, Error> {
+        if reader.request.path() == "/private" {
+            LOG.lock().unwrap().push("deny");
+
+            return writer
+                .start(StatusCode::FORBIDDEN)
+                .await?
+                .body_empty()
+                .await;
+        }
+
+        next.run(config, state, reader, writer).await
+    }
+}
+
+#[test]
+fn layers() {
+    let config = HttpConfig::default();
+    let server = HttpServer::with_config(&config)
+        .route(router! {
+            GET "/" => hello,
+            GET "/private" => hello,
+        })
+        .layer(Tag("X-First"))
+        .layer(Deny)
+        .layer(Tag("X-Second"));
+
+    // the middlewares run in the order they were added, and their headers end up on the handler's response
+    send(&server, b"GET / HTTP/1.1\r\n\r\n", |response| {
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body_str(), Some("hello"));
+        assert_eq!(response.header("X-First"), Some("yes"));
+        assert_eq!(response.header("X-Second"), Some("yes"));
+    });
+    assert_eq!(
+        LOG.lock().unwrap().drain(..).collect::<Vec<_>>(),
+        ["X-First", "X-Second", "handler"]
+    );
+
+    // a middleware that answers stops the request there, and only has the headers added before it
+    send(&server, b"GET /private HTTP/1.1\r\n\r\n", |response| {
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(response.header("X-First"), Some("yes"));
+        assert_eq!(response.header("X-Second"), None);
+    });
+    assert_eq!(
+        LOG.lock().unwrap().drain(..).collect::<Vec<_>>(),
+        ["X-First", "deny"]
+    );
+}
@@ -0,0 +1,355 @@
+mod common;
+
+use tinyhttp::config::{HttpConfig, StaticPage};
+use tinyhttp::error::Error;
+use tinyhttp::reader::RequestReader;
+use tinyhttp::request::HttpMethod;
+use tinyhttp::status::StatusCode;
+use tinyhttp::testing::{ConnectionState, TestResponse};
+use tinyhttp::transport::Transport;
+use tinyhttp::writer::{HttpResponse, ResponseWriter};
+use tinyhttp::{router, HttpServer};
+
+use common::send;
+
+async fn hello<T: Transport>(
+    _reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    writer
+        .start(StatusCode::OK)
+        .await?
+        .body_str("hello", "text/plain")
+        .await
+}
+
+#[test]
+fn test_request() {
+    let config = HttpConfig::default();
+    let server = HttpServer::with_config(&config).route(router! {
+        "/" => hello,
+    });
+
+    send(&server, b"GET / HTTP/1.1\r\n\r\n", |response| {
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.header("content-type"), Some("text/plain"));
+        assert_eq!(response.body_str(), Some("hello"));
+        assert_eq!(response.connection(), ConnectionState::Closed);
+    });
+}
+
+#[test]
+fn test_responses() {
+    let mut raw = *b"HTTP/1.1 304 Not Modified\r\nETag: \"a\"\r\n\r\nHTTP/1.1 200 OK\r\n\r\n";
+    let response =
+        TestResponse::parse(&mut raw, Some(HttpMethod::Get), ConnectionState::Open).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.body(), b"");
+
+    let mut raw = *b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n";
+    let response =
+        TestResponse::parse(&mut raw, Some(HttpMethod::Head), ConnectionState::Open).unwrap();
+    assert_eq!(response.header("content-length"), Some("5"));
+    assert_eq!(response.body(), b"");
+
+    let mut raw = *b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n";
+    assert_eq!(
+        TestResponse::parse(&mut raw, Some(HttpMethod::Get), ConnectionState::Open).unwrap_err(),
+        Error::BadRequest
+    );
+}
+
+#[test]
+fn keep_alive() {
+    let config = HttpConfig::default();
+    let server = HttpServer::with_config(&config).route(router! {
+        "/" => hello,
+    });
+
+    let raw = send(
+        &server,
+        b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        |response| {
+            assert_eq!(response.header("Connection"), Some("keep-alive"));
+            assert_eq!(response.body_str(), Some("hello"));
+            // the client closed the connection after the second request
+            assert_eq!(response.connection(), ConnectionState::Closed);
+        },
+    );
+    assert_eq!(raw.matches("HTTP/1.1 200 OK").count(), 2, "{raw}");
+
+    let raw = send(
+        &server,
+        b"GET / HTTP/1.1\r\nConnection: close\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        |response| {
+            assert_eq!(response.header("Connection"), Some("close"));
+        },
+    );
+    assert_eq!(raw.matches("HTTP/1.1 200 OK").count(), 1, "{raw}");
+
+    let raw = send(
+        &server,
+        b"GET / HTTP/1.0\r\n\r\nGET / HTTP/1.0\r\n\r\n",
+        |response| {
+            assert_eq!(response.header("Connection"), Some("close"));
+        },
+    );
+    assert_eq!(raw.matches("HTTP/1.0 200 OK").count(), 1, "{raw}");
+
+    let config = HttpConfig {
+        keepalive: None,
+        ..HttpConfig::default()
+    };
+    let server = HttpServer::with_config(&config).route(router! {
+        "/" => hello,
+    });
+    let raw = send(
+        &server,
+        b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        |response| {
+            assert_eq!(response.header("Connection"), Some("close"));
+        },
+    );
+    assert_eq!(raw.matches("HTTP/1.1 200 OK").count(), 1, "{raw}");
+}
+
+/// Answers without reading the request body.
+async fn ignore_body<T: Transport>(
+    _reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    writer
+        .start(StatusCode::NO_CONTENT)
+        .await?
+        .body_empty()
+        .await
+}
+
+#[test]
+fn unread_bodies() {
+    let config = HttpConfig::default();
+    let server = HttpServer::with_config(&config).route(router! {
+        GET "/" => hello,
+        POST "/ignore" => ignore_body,
+    });
+
+    let request =
+        b"POST /ignore HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello worldGET / HTTP/1.1\r\n\r\n";
+    let raw = send(&server, request, |response| {
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.header("Connection"), Some("keep-alive"));
+    });
+    assert!(raw.ends_with("\r\n\r\nhello"), "{raw}");
+
+    let request = b"POST /ignore HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+    let raw = send(&server, request, |_| {});
+    assert!(raw.ends_with("\r\n\r\nhello"), "{raw}");
+
+    // bodies larger than the drain limit close the connection
+    let config = HttpConfig {
+        body_drain_limit: 4,
+        ..HttpConfig::default()
+    };
+    let server = HttpServer::with_config(&config).route(router! {
+        GET "/" => hello,
+        POST "/ignore" => ignore_body,
+    });
+    let request =
+        b"POST /ignore HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello worldGET / HTTP/1.1\r\n\r\n";
+    let raw = send(&server, request, |response| {
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.connection(), ConnectionState::Closed);
+    });
+    assert!(!raw.contains("hello"), "{raw}");
+}
+
+async fn stream<T: Transport>(
+    _reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    let mut body = writer
+        .start(StatusCode::OK)
+        .await?
+        .body_chunked(5, "text/plain")
+        .await?;
+
+    for chunk in ["ab", "cde", ""] {
+        if let Some(response) = body.write_chunk_str(chunk).await? {
+            return Ok(response);
+        }
+    }
+
+    unreachable!("the whole body was written")
+}
+
+async fn chunked<T: Transport>(
+    _reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    let mut body = writer
+        .start(StatusCode::OK)
+        .await?
+        .body_chunked_encoding("text/plain")
+        .await?;
+
+    body.write_chunk_str("ab").await?;
+    body.write_chunk_str("cde").await?;
+    body.finish().await
+}
+
+#[test]
+fn streamed_bodies() {
+    let config = HttpConfig::default();
+    let server = HttpServer::with_config(&config).route(router! {
+        "/" => hello,
+        "/stream" => stream,
+        "/chunked" => chunked,
+    });
+
+    send(&server, b"GET /stream HTTP/1.1\r\n\r\n", |response| {
+        assert_eq!(response.header("Content-Length"), Some("5"));
+        assert_eq!(response.header("Transfer-Encoding"), None);
+        assert_eq!(response.body_str(), Some("abcde"));
+    });
+
+    let raw = send(
+        &server,
+        b"GET /chunked HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        |response| {
+            assert_eq!(response.header("Content-Length"), None);
+            assert_eq!(response.header("Transfer-Encoding"), Some("chunked"));
+            assert_eq!(response.body_str(), Some("abcde"));
+        },
+    );
+    // the next response starts right after the last chunk
+    assert!(raw.contains("\r\n0\r\n\r\nHTTP/1.1 200 OK\r\n"), "{raw}");
+
+    // HTTP/1.0 clients don't support chunked encoding, the end of the body is marked by closing the connection
+    let raw = send(
+        &server,
+        b"GET /chunked HTTP/1.0\r\n\r\nGET / HTTP/1.0\r\n\r\n",
+        |response| {
+            assert_eq!(response.header("Connection"), Some("close"));
+            assert_eq!(response.body_str(), Some("abcde"));
+        },
+    );
+    assert!(raw.ends_with("\r\n\r\nabcde"), "{raw}");
+}
+
+async fn echo<T: Transport>(
+    reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    let mut buf = [0u8; 64];
+    let mut len = 0;
+
+    if let Some(mut body) = reader.body() {
+        loop {
+            let count = body.try_read(&mut buf[len..]).await?;
+            if count == 0 {
+                break;
+            }
+            len += count;
+        }
+    }
+
+    writer
+        .start(StatusCode::OK)
+        .await?
+        .body_bytes(&buf[..len], "application/octet-stream")
+        .await
+}
+
+#[test]
+fn chunked_uploads() {
+    let config = HttpConfig::default();
+    let server = HttpServer::with_config(&config).route(router! {
+        GET "/" => hello,
+        POST "/echo" => echo,
+    });
+
+    let request = b"POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3;name=value\r\nabc\r\n2\nde\n0\r\nX-Checksum: 1\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+    let raw = send(&server, request, |response| {
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body_str(), Some("abcde"));
+    });
+    // the next request is read right after the trailers
+    assert!(raw.ends_with("\r\n\r\nhello"), "{raw}");
+
+    // a chunked body ignores the Content-Length header
+    let request = b"POST /echo HTTP/1.1\r\nContent-Length: 100\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nab\r\n0\r\n\r\n";
+    send(&server, request, |response| {
+        assert_eq!(response.body_str(), Some("ab"));
+    });
+
+    let request = b"POST /echo HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n";
+    send(&server, request, |response| {
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    });
+}
+
+/// Fails before starting the response, so the server sends an error page.
+async fn fail<T: Transport>(
+    _reader: RequestReader<'_, '_, '_, T>,
+    _writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    Err(Error::BadRequest)
+}
+
+#[test]
+fn head() {
+    let config = HttpConfig {
+        http_400: Some(StaticPage::html("<h1>bad request</h1>")),
+        ..HttpConfig::default()
+    };
+    let server = HttpServer::with_config(&config).route(router! {
+        "/" => hello,
+        "/stream" => stream,
+        "/chunked" => chunked,
+        "/fail" => fail,
+    });
+
+    let raw = send(
+        &server,
+        b"HEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        |response| {
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.header("Content-Length"), Some("5"));
+            assert_eq!(response.header("Connection"), Some("keep-alive"));
+        },
+    );
+    // only the GET request gets the body
+    assert_eq!(raw.matches("hello").count(), 1, "{raw}");
+    assert!(raw.contains("keep-alive\r\n\r\nHTTP/1.1 200 OK"), "{raw}");
+
+    send(&server, b"HEAD /stream HTTP/1.1\r\n\r\n", |response| {
+        assert_eq!(response.header("Content-Length"), Some("5"));
+    });
+    let raw = send(&server, b"HEAD /chunked HTTP/1.1\r\n\r\n", |response| {
+        assert_eq!(response.header("Transfer-Encoding"), Some("chunked"));
+    });
+    assert!(raw.ends_with("\r\n\r\n"), "{raw}");
+
+    // HTTP/1.0 clients never get a chunked response
+    let raw = send(&server, b"HEAD /chunked HTTP/1.0\r\n\r\n", |response| {
+        assert_eq!(response.header("Transfer-Encoding"), None);
+    });
+    assert!(raw.ends_with("\r\n\r\n"), "{raw}");
+
+    // error pages don't have a body either, whether the handler or the request failed
+    let raw = send(&server, b"GET /fail HTTP/1.1\r\n\r\n", |response| {
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.body_str(), Some("<h1>bad request</h1>"));
+    });
+    assert!(!raw.ends_with("\r\n\r\n"), "{raw}");
+    for request in [
+        &b"HEAD /fail HTTP/1.1\r\n\r\n"[..],
+        b"HEAD / HTTP/1.1\r\nnot a header\r\n\r\n",
+    ] {
+        let raw = send(&server, request, |response| {
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            assert!(response.header("Content-Length").is_some());
+        });
+        assert!(raw.ends_with("\r\n\r\n"), "{raw}");
+    }
+}
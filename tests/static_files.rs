@@ -0,0 +1,370 @@
+mod common;
+
+use tinyhttp::config::{HttpConfig, StaticPage};
+use tinyhttp::error::Error;
+use tinyhttp::files::{embed_dir, StaticDir};
+use tinyhttp::reader::RequestReader;
+use tinyhttp::status::StatusCode;
+use tinyhttp::testing::TestResponse;
+use tinyhttp::transport::Transport;
+use tinyhttp::writer::{HttpResponse, ResponseWriter};
+use tinyhttp::{router, HttpServer};
+
+fn cache_control(path: &str) -> Option<&'static str> {
+    if path.ends_with(".html") {
+        Some("no-cache")
+    } else {
+        Some("max-age=31536000, immutable")
+    }
+}
+
+static WEB_UI: StaticDir = embed_dir!("tests/webui").with_cache_control(cache_control);
+
+const FAVICON: StaticPage = StaticPage::from_extension("ico", &[0, 1, 255]);
+const STYLE: StaticPage = StaticPage::from_extension("css", b"body{}").hashed();
+const DATA: StaticPage = StaticPage::from_extension("unknown-extension", b"data");
+
+async fn favicon<T: Transport>(
+    _reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    writer.static_page(FAVICON, StatusCode::OK).await
+}
+
+async fn style<T: Transport>(
+    _reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    writer.static_page(STYLE, StatusCode::OK).await
+}
+
+async fn data<T: Transport>(
+    _reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    writer.static_page(DATA, StatusCode::OK).await
+}
+
+async fn firmware<T: Transport>(
+    _reader: RequestReader<'_, '_, '_, T>,
+    writer: ResponseWriter<'_, '_, T>,
+) -> Result<HttpResponse, Error> {
+    writer
+        .ranged_bytes(b"0123456789", "application/octet-stream")
+        .await
+}
+
+/// Sends the raw `request` to a server with the static pages, and passes its response to `check`.
+fn request(request: &str, check: impl FnOnce(TestResponse)) {
+    let config = HttpConfig::default();
+    let server = HttpServer::with_config(&config).route(router! {
+        GET "/favicon.ico" => favicon,
+        GET "/style.css" => style,
+        GET "/data" => data,
+        GET "/firmware.bin" => firmware,
+        _ => WEB_UI,
+    });
+
+    common::send(&server, request.as_bytes(), check);
+}
+
+/// Sends a GET request for `path` with `headers`, and passes the response to `check`.
+fn get(path: &str, headers: &str, check: impl FnOnce(TestResponse)) {
+    request(&format!("GET {path} HTTP/1.1\r\n{headers}\r\n"), check);
+}
+
+#[test]
+fn content_types() {
+    get("/favicon.ico", "", |response| {
+        assert_eq!(response.header("Content-Type"), Some("image/x-icon"));
+        assert_eq!(response.header("Content-Length"), Some("3"));
+        assert_eq!(response.body(), &[0, 1, 255]);
+    });
+    get("/style.css", "", |response| {
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/css; charset=UTF-8")
+        );
+    });
+    get("/data", "", |response| {
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("application/octet-stream")
+        );
+    });
+}
+
+#[test]
+fn files() {
+    let paths: Vec<_> = WEB_UI.files().iter().map(|file| file.path).collect();
+    assert_eq!(
+        paths,
+        [
+            "/alone.txt.gz",
+            "/css/app.css",
+            "/index.html",
+            "/my icon.png",
+            "/sub/index.html"
+        ]
+    );
+
+    get("/", "", |response| {
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/html; charset=UTF-8")
+        );
+        assert_eq!(response.body_str(), Some("<h1>hi</h1>"));
+    });
+    get("/sub/", "", |response| {
+        assert_eq!(response.body_str(), Some("sub"));
+    });
+    get("/my%20icon.png", "", |response| {
+        assert_eq!(response.header("Content-Type"), Some("image/png"));
+        assert_eq!(response.body(), b"\x89PNG");
+    });
+    // embedded files get the same content type as pages created by hand
+    get("/css/app.css", "", |response| {
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/css; charset=UTF-8")
+        );
+        assert_eq!(response.body(), b"body{}");
+    });
+
+    for path in ["/.secret", "/sub", "/css/app.css.gz", "/missing"] {
+        get(path, "", |response| {
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{path}");
+        });
+    }
+
+    request("POST /index.html HTTP/1.1\r\n\r\n", |response| {
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.header("Allow"), Some("GET, HEAD, OPTIONS"));
+    });
+}
+
+#[test]
+fn encodings() {
+    get(
+        "/css/app.css",
+        "Accept-Encoding: gzip, deflate, br\r\n",
+        |response| {
+            assert_eq!(response.header("Content-Encoding"), Some("br"));
+            assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+            assert_eq!(response.body(), b"BR");
+        },
+    );
+    get(
+        "/css/app.css",
+        "Accept-Encoding: br;q=0.5, gzip\r\n",
+        |response| {
+            assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+            assert_eq!(response.body(), b"GZ");
+        },
+    );
+    get("/css/app.css", "Accept-Encoding: br;q=0\r\n", |response| {
+        assert_eq!(response.header("Content-Encoding"), None);
+        assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+        assert_eq!(response.body(), b"body{}");
+    });
+    get("/css/app.css", "", |response| {
+        assert_eq!(response.header("Content-Encoding"), None);
+        assert_eq!(response.body(), b"body{}");
+    });
+
+    // files without a compressed variant don't depend on the encodings
+    get("/index.html", "Accept-Encoding: gzip\r\n", |response| {
+        assert_eq!(response.header("Content-Encoding"), None);
+        assert_eq!(response.header("Vary"), None);
+    });
+}
+
+/// Gets the ETag of the page at `path`, sent with `headers`.
+fn etag_of(path: &str, headers: &str) -> String {
+    let mut etag = String::new();
+    get(path, headers, |response| {
+        etag = response.header("ETag").unwrap().to_string();
+    });
+
+    etag
+}
+
+#[test]
+fn conditional_requests() {
+    let etag = etag_of("/", "");
+    assert!(etag.starts_with('"') && etag.ends_with('"'), "{etag}");
+    // embedded files are hashed like the pages created by hand
+    assert_eq!(etag_of("/css/app.css", ""), etag_of("/style.css", ""));
+
+    get("/", "", |response| {
+        assert_eq!(response.header("Cache-Control"), Some("no-cache"));
+    });
+    get("/css/app.css", "", |response| {
+        assert_eq!(
+            response.header("Cache-Control"),
+            Some("max-age=31536000, immutable")
+        );
+    });
+
+    get(
+        "/",
+        &format!("If-None-Match: \"old\", W/{etag}\r\n"),
+        |response| {
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(response.header("ETag"), Some(&etag[..]));
+            assert_eq!(response.header("Cache-Control"), Some("no-cache"));
+            assert_eq!(response.header("Content-Length"), None);
+            assert_eq!(response.body(), b"");
+        },
+    );
+    get("/", "If-None-Match: \"old\"\r\n", |response| {
+        assert_eq!(response.status(), StatusCode::OK);
+    });
+    request("HEAD / HTTP/1.1\r\nIf-None-Match: *\r\n\r\n", |response| {
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    });
+
+    // If-Match uses the strong comparison
+    get("/", &format!("If-Match: W/{etag}\r\n"), |response| {
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(response.body(), b"");
+    });
+    get("/", "If-Match: \"old\"\r\n", |response| {
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    });
+    get("/", &format!("If-Match: \"old\", {etag}\r\n"), |response| {
+        assert_eq!(response.status(), StatusCode::OK);
+    });
+    get("/", "If-Match: *\r\n", |response| {
+        assert_eq!(response.status(), StatusCode::OK);
+    });
+
+    // compressed variants have their own ETag
+    let gzip_etag = etag_of("/css/app.css", "Accept-Encoding: gzip\r\n");
+    assert_ne!(gzip_etag, etag_of("/css/app.css", ""));
+
+    get(
+        "/css/app.css",
+        &format!("If-None-Match: {gzip_etag}\r\n"),
+        |response| {
+            assert_eq!(response.status(), StatusCode::OK);
+        },
+    );
+    get(
+        "/css/app.css",
+        &format!("Accept-Encoding: gzip\r\nIf-None-Match: {gzip_etag}\r\n"),
+        |response| {
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(response.header("ETag"), Some(&gzip_etag[..]));
+            assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+        },
+    );
+}
+
+#[test]
+fn ranges() {
+    get("/firmware.bin", "", |response| {
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.header("Accept-Ranges"), Some("bytes"));
+        assert_eq!(response.body(), b"0123456789");
+    });
+
+    for (range, content_range, body) in [
+        ("bytes=2-4", "bytes 2-4/10", &b"234"[..]),
+        ("bytes=7-", "bytes 7-9/10", b"789"),
+        ("bytes=-3", "bytes 7-9/10", b"789"),
+        ("bytes=8-100", "bytes 8-9/10", b"89"),
+        ("bytes=-100", "bytes 0-9/10", b"0123456789"),
+    ] {
+        get(
+            "/firmware.bin",
+            &format!("Range: {range}\r\n"),
+            |response| {
+                assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT, "{range}");
+                assert_eq!(response.header("Content-Range"), Some(content_range));
+                assert_eq!(response.header("Accept-Ranges"), Some("bytes"));
+                assert_eq!(response.body(), body);
+            },
+        );
+    }
+
+    for range in ["bytes=10-", "bytes=-0"] {
+        get(
+            "/firmware.bin",
+            &format!("Range: {range}\r\n"),
+            |response| {
+                assert_eq!(
+                    response.status(),
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    "{range}"
+                );
+                assert_eq!(response.header("Content-Range"), Some("bytes */10"));
+                assert_eq!(response.body(), b"");
+            },
+        );
+    }
+
+    // ranges that can't be parsed or aren't supported get the whole body
+    for range in ["bytes=0-1,3-4", "items=0-1", "bytes=a-b", "bytes=20-10"] {
+        get(
+            "/firmware.bin",
+            &format!("Range: {range}\r\n"),
+            |response| {
+                assert_eq!(response.status(), StatusCode::OK, "{range}");
+                assert_eq!(response.body(), b"0123456789");
+            },
+        );
+    }
+    request(
+        "HEAD /firmware.bin HTTP/1.1\r\nRange: bytes=0-1\r\n\r\n",
+        |response| {
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.header("Content-Length"), Some("10"));
+        },
+    );
+}
+
+#[test]
+fn static_page_ranges() {
+    let etag = etag_of("/", "");
+
+    get("/", "Range: bytes=4-5\r\n", |response| {
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.header("Content-Range"), Some("bytes 4-5/11"));
+        assert_eq!(response.header("ETag"), Some(&etag[..]));
+        assert_eq!(response.body_str(), Some("hi"));
+    });
+    get("/", "Range: bytes=11-\r\n", |response| {
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.header("Content-Range"), Some("bytes */11"));
+    });
+
+    // ranges of a page the client doesn't have anymore are ignored
+    get(
+        "/",
+        &format!("Range: bytes=4-5\r\nIf-Range: {etag}\r\n"),
+        |response| {
+            assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        },
+    );
+    get(
+        "/",
+        "Range: bytes=4-5\r\nIf-Range: \"old\"\r\n",
+        |response| {
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.body_str(), Some("<h1>hi</h1>"));
+        },
+    );
+
+    // ranges apply to the compressed variant that is sent
+    get(
+        "/css/app.css",
+        "Accept-Encoding: gzip\r\nRange: bytes=0-0\r\n",
+        |response| {
+            assert_eq!(response.header("Content-Range"), Some("bytes 0-0/2"));
+            assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+            assert_eq!(response.body(), b"G");
+        },
+    );
+}